@@ -0,0 +1,66 @@
+// cargo bench --bench eval, 同一台机器上的中位数:
+//
+//                 按名字查变量   resolve成槽位   函数体和循环体不再复制   全局变量也用槽位
+// loop_sum        7.08 ms        4.40 ms         1.81 ms                  1.73 ms
+// fib             5.02 ms        4.20 ms         1.45 ms                  1.31 ms
+// nested_scopes   12.13 ms       8.56 ms         3.18 ms                  3.00 ms
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use z_lang::pest::slick_script::run_code;
+
+// 函数里的循环, 每次都要读写局部变量
+const LOOP_SUM: &str = r#"
+int sum_to (int n) {
+  int sum = 0;
+  for (int i = 0; i < n; i = i + 1) {
+    sum = sum + i;
+  }
+  return sum;
+}
+sum_to(5000);
+"#;
+
+// 递归调用, 每层都要找到全局的函数和自己的参数
+const FIB: &str = r#"
+int fib (int n) {
+  if (n < 2) { return n; }
+  return fib(n - 1) + fib(n - 2);
+}
+fib(15);
+"#;
+
+// 变量声明在外面几层, 在最里层的循环里读
+const NESTED_SCOPES: &str = r#"
+int nested (int n) {
+  int total = 0;
+  int step = 1;
+  {
+    {
+      int i = 0;
+      while (i < n) {
+        if (i >= 0) {
+          total = total + step;
+        }
+        i = i + step;
+      }
+    }
+  }
+  return total;
+}
+nested(5000);
+"#;
+
+fn bench_eval(c: &mut Criterion) {
+    for (name, code) in [
+        ("loop_sum", LOOP_SUM),
+        ("fib", FIB),
+        ("nested_scopes", NESTED_SCOPES),
+    ] {
+        c.bench_function(name, |b| {
+            b.iter(|| run_code(code.to_string()).unwrap_or_else(|e| panic!("{}", e)))
+        });
+    }
+}
+
+criterion_group!(benches, bench_eval);
+criterion_main!(benches);
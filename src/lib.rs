@@ -1,4 +1,3 @@
+pub mod pest;
 
-mod pest;
-
-mod util;
\ No newline at end of file
+pub mod util;
\ No newline at end of file
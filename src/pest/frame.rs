@@ -1,49 +1,147 @@
 use std::cell::RefCell;
 use std::cmp::PartialEq;
 use std::cmp::PartialOrd;
-use std::convert::Into;
+use std::fmt;
 use std::fmt::Debug;
 use std::{collections::HashMap, rc::Rc};
 
-use super::parse_ast::AstNodeType;
+use indexmap::IndexMap;
+
+use super::parse_ast::{AstNodeType, DeclareType};
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests;
 
 #[derive(Debug)]
 pub enum ValType {
+    // 语句和void函数的结果, 没有值
+    Unit,
     String(String),
     Number(i32),
+    Long(i64),
+    Float(f64),
     Boolean(bool),
+    // 数组, 值通过Rc共享, 赋值和传参都是引用同一个数组
+    Array(RefCell<Vec<Rc<ValType>>>),
+    // map, 按插入顺序保存, 和数组一样是引用
+    Map(RefCell<IndexMap<MapKey, Rc<ValType>>>),
+    // 结构体, 字段按声明顺序保存, 和数组一样是引用
+    Struct {
+        name: String,
+        fields: RefCell<IndexMap<String, Rc<ValType>>>,
+    },
+    // 枚举的一个成员, 带的值按声明顺序保存
+    Enum {
+        name: String,
+        variant: String,
+        values: Vec<Rc<ValType>>,
+    },
     Closure {
         // 定义函数时, 当时的作用域
         scope: Rc<RefCell<Scope>>,
         block: Rc<AstNodeType>,
         name: String,
-        // AstNodeType里面的Declaration
-        args: Vec<AstNodeType>,
+        // AstNodeType里面的Declaration, 和函数定义共用
+        args: Rc<Vec<AstNodeType>>,
+        // 声明了返回类型, 执行完没有return要报错
+        returns: bool,
     },
 }
 
-impl Into<i32> for ValType {
-    fn into(self) -> i32 {
+/**
+ * map的key, 只能是int或者string
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Number(i32),
+    String(String),
+}
+
+impl MapKey {
+    pub fn from_val(val: &ValType) -> Option<MapKey> {
+        match val {
+            ValType::Number(n) => Some(MapKey::Number(*n)),
+            ValType::String(s) => Some(MapKey::String(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_val(&self) -> ValType {
         match self {
-            ValType::Number(num) => num,
-            _ => panic!("Cannot convert ValType to the target type"),
+            MapKey::Number(n) => ValType::Number(*n),
+            MapKey::String(s) => ValType::String(s.clone()),
         }
     }
 }
 
-impl PartialEq for ValType {
-    fn ne(&self, other: &Self) -> bool {
-        match (self, other) {
-            (ValType::Number(s), ValType::Number(o)) => s != o,
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapKey::Number(n) => write!(f, "{}", n),
+            MapKey::String(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+impl ValType {
+    /**
+     * 运行时的类型名, 用在错误信息里
+     */
+    pub fn type_name(&self) -> String {
+        match self {
+            ValType::Unit => String::from("void"),
+            ValType::String(_) => String::from("string"),
+            ValType::Number(_) => String::from("int"),
+            ValType::Long(_) => String::from("long"),
+            ValType::Float(_) => String::from("float"),
+            ValType::Boolean(_) => String::from("bool"),
+            ValType::Array(_) => String::from("数组"),
+            ValType::Map(_) => String::from("map"),
+            ValType::Struct { name, .. } | ValType::Enum { name, .. } => name.clone(),
+            ValType::Closure { .. } => String::from("函数"),
+        }
+    }
+
+    /**
+     * 值是不是声明的类型
+     * 数组, map和函数只看外层, 不检查元素和参数
+     */
+    pub fn is_type(&self, declare_type: &DeclareType) -> bool {
+        match (self, declare_type) {
+            (ValType::String(_), DeclareType::String)
+            | (ValType::Number(_), DeclareType::Int)
+            | (ValType::Long(_), DeclareType::Long)
+            | (ValType::Float(_), DeclareType::Float)
+            | (ValType::Boolean(_), DeclareType::Bool)
+            | (ValType::Array(_), DeclareType::Array(_))
+            | (ValType::Map(_), DeclareType::Map { .. })
+            | (ValType::Closure { .. }, DeclareType::FnType { .. }) => true,
+            (ValType::Struct { name, .. }, DeclareType::Named(declared))
+            | (ValType::Enum { name, .. }, DeclareType::Named(declared)) => name == declared,
             _ => false,
         }
     }
+}
+
+impl From<ValType> for i32 {
+    fn from(val: ValType) -> i32 {
+        match val {
+            ValType::Number(num) => num,
+            _ => panic!("Cannot convert ValType to the target type"),
+        }
+    }
+}
+
+impl PartialEq for ValType {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (ValType::Number(s), ValType::Number(o)) => s == o,
+            (ValType::Long(s), ValType::Long(o)) => s == o,
+            (ValType::Float(s), ValType::Float(o)) => s == o,
+            (ValType::Boolean(s), ValType::Boolean(o)) => s == o,
+            (ValType::String(s), ValType::String(o)) => s == o,
+            (ValType::Unit, ValType::Unit) => true,
             _ => false,
         }
     }
@@ -53,6 +151,9 @@ impl PartialOrd for ValType {
     fn gt(&self, other: &Self) -> bool {
         match (self, other) {
             (ValType::Number(s), ValType::Number(o)) => s > o,
+            (ValType::Long(s), ValType::Long(o)) => s > o,
+            (ValType::Float(s), ValType::Float(o)) => s > o,
+            (ValType::String(s), ValType::String(o)) => s > o,
             _ => false,
         }
     }
@@ -60,6 +161,9 @@ impl PartialOrd for ValType {
     fn ge(&self, other: &Self) -> bool {
         match (self, other) {
             (ValType::Number(s), ValType::Number(o)) => s >= o,
+            (ValType::Long(s), ValType::Long(o)) => s >= o,
+            (ValType::Float(s), ValType::Float(o)) => s >= o,
+            (ValType::String(s), ValType::String(o)) => s >= o,
             _ => false,
         }
     }
@@ -67,6 +171,9 @@ impl PartialOrd for ValType {
     fn le(&self, other: &Self) -> bool {
         match (self, other) {
             (ValType::Number(s), ValType::Number(o)) => s <= o,
+            (ValType::Long(s), ValType::Long(o)) => s <= o,
+            (ValType::Float(s), ValType::Float(o)) => s <= o,
+            (ValType::String(s), ValType::String(o)) => s <= o,
             _ => false,
         }
     }
@@ -74,11 +181,14 @@ impl PartialOrd for ValType {
     fn lt(&self, other: &Self) -> bool {
         match (self, other) {
             (ValType::Number(s), ValType::Number(o)) => s < o,
+            (ValType::Long(s), ValType::Long(o)) => s < o,
+            (ValType::Float(s), ValType::Float(o)) => s < o,
+            (ValType::String(s), ValType::String(o)) => s < o,
             _ => false,
         }
     }
 
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
         None
     }
 }
@@ -87,6 +197,23 @@ impl PartialOrd for ValType {
 pub struct Frame {
     pub parent_frame: Option<Rc<RefCell<Frame>>>,
     pub scope: Rc<RefCell<Scope>>,
+    // 调用的层数, 全局是0, 每调用一次函数加1
+    pub depth: usize,
+    // 调用层数的上限, 新的frame从调用方继承
+    pub max_depth: usize,
+    // 全局变量, 所有frame共用一份
+    pub globals: Rc<RefCell<Globals>>,
+}
+
+/**
+ * 全局变量, resolve按名字分配槽位, 执行时只按槽位存取
+ * 名字到槽位的表在多次eval_top之间保留, 后面的语句用到同一个名字时拿到同一个槽位
+ */
+#[derive(Debug, Default)]
+pub struct Globals {
+    pub names: HashMap<String, usize>,
+    // 还没执行到声明的槽位是None
+    pub slots: Vec<Option<Rc<ValType>>>,
 }
 
 
@@ -104,6 +231,32 @@ impl Frame {
         true
     }
 
+    /**
+     * 按resolve算出的位置取局部变量, 从当前作用域往外数depth层
+     */
+    pub fn get_slot(&self, depth: usize, slot: usize) -> Option<Rc<ValType>> {
+        self.scope.borrow().get_slot(depth, slot)
+    }
+
+    pub fn set_slot(&mut self, depth: usize, slot: usize, val: Rc<ValType>) -> bool {
+        self.scope.borrow_mut().set_slot(depth, slot, val)
+    }
+
+    /**
+     * 按resolve分配的槽位取全局变量
+     */
+    pub fn get_global(&self, slot: usize) -> Option<Rc<ValType>> {
+        self.globals.borrow().slots.get(slot).cloned().flatten()
+    }
+
+    pub fn set_global(&mut self, slot: usize, val: Rc<ValType>) {
+        let mut globals = self.globals.borrow_mut();
+        if slot >= globals.slots.len() {
+            globals.slots.resize(slot + 1, None);
+        }
+        globals.slots[slot] = Some(val);
+    }
+
     pub fn create_scope(&mut self, new_scope: Rc<RefCell<Scope>>) -> bool {
         new_scope.borrow_mut().set_parent(self.scope.clone());
         self.scope = new_scope;
@@ -138,10 +291,13 @@ pub enum Scope {
 
 /**
  * 块级作用域
+ * play_object按名字存变量, 脚本里的局部变量按resolve分配的槽位存在slots里
  */
 #[derive(Debug)]
 pub struct ScopeStruct {
     pub play_object: HashMap<String, Rc<ValType>>,
+    // 还没执行到声明的槽位是None
+    pub slots: Vec<Option<Rc<ValType>>>,
     pub parent_scope: Option<Rc<RefCell<Scope>>>,
 }
 
@@ -177,6 +333,20 @@ impl Scope {
             }
         }
     }
+    fn get_slot(&self, depth: usize, slot: usize) -> Option<Rc<ValType>> {
+        match self {
+            Scope::Closure(scope_struct) | Scope::Block(scope_struct) => {
+                scope_struct.get_slot(depth, slot)
+            }
+        }
+    }
+    fn set_slot(&mut self, depth: usize, slot: usize, val: Rc<ValType>) -> bool {
+        match self {
+            Scope::Closure(scope_struct) | Scope::Block(scope_struct) => {
+                scope_struct.set_slot(depth, slot, val)
+            }
+        }
+    }
 
     // fn drop(&mut self) -> bool {
     //     match self {
@@ -188,31 +358,23 @@ impl Scope {
 }
 
 impl ScopeStruct {
-    fn new(parent_scope: Option<Rc<RefCell<Scope>>>) -> Self
+    pub fn new(parent_scope: Option<Rc<RefCell<Scope>>>) -> Self
     where
         Self: Sized,
     {
         ScopeStruct {
             play_object: HashMap::new(),
+            slots: vec![],
             parent_scope,
         }
     }
 
     fn get(&self, q: &String) -> Option<Rc<ValType>> {
         match self.play_object.get(q) {
-            Some(val) => return Some(val.clone()),
-            None => {
-                // 没有的话就从父级找
-                let val = match self.get_parent_scope() {
-                    Some(parent) => {
-                        return parent.borrow().get(q);
-                    },
-                    None => None,
-                };
-                val
-            },
+            Some(val) => Some(val.clone()),
+            // 没有的话就从父级找
+            None => self.parent_scope.as_ref()?.borrow().get(q),
         }
-
     }
 
     fn get_parent_scope(&self) -> Option<Rc<RefCell<Scope>>> {
@@ -220,40 +382,24 @@ impl ScopeStruct {
     }
 
     fn set(&mut self, q: String, val: Rc<ValType>, is_declare: bool) -> bool {
-        if is_declare {
+        if is_declare || self.play_object.contains_key(&q) {
             self.play_object.insert(q, val);
             return true;
         }
-        match self.play_object.contains_key(&q) {
-            true => {
-                self.play_object.insert(q, val);
-                return true
-            },
-            false => {
-                // 没有的话就从父级找
-                 match self.get_parent_scope() {
-                    Some(parent) => {
-                        return parent.borrow_mut().set(q, val, false);
-                    },
-                    None => return false,
-                }
-            },
+        // 没有的话就从父级找
+        match &self.parent_scope {
+            Some(parent) => parent.borrow_mut().set(q, val, false),
+            None => false,
         }
     }
 
     fn contains_key(&self, q: &String) -> bool {
-        match self.play_object.contains_key(q) {
-            true => return true,
-            false => {
-                // 没有的话就从父级找
-                 match self.get_parent_scope() {
-                    Some(parent) => {
-                        return parent.borrow().contains_key(q);
-                    },
-                    None => return false,
-                }
-            },
-        }
+        // 没有的话就从父级找
+        self.play_object.contains_key(q)
+            || self
+                .parent_scope
+                .as_ref()
+                .is_some_and(|parent| parent.borrow().contains_key(q))
     }
 
     fn set_parent(&mut self, parent: Rc<RefCell<Scope>>) -> bool {
@@ -261,5 +407,29 @@ impl ScopeStruct {
         true
     }
 
+    fn get_slot(&self, depth: usize, slot: usize) -> Option<Rc<ValType>> {
+        if depth == 0 {
+            return self.slots.get(slot).cloned().flatten();
+        }
+        // 往外走depth层, 每层只跳到父作用域, 不用按名字查
+        self.parent_scope
+            .as_ref()?
+            .borrow()
+            .get_slot(depth - 1, slot)
+    }
+
+    fn set_slot(&mut self, depth: usize, slot: usize, val: Rc<ValType>) -> bool {
+        if depth > 0 {
+            return match &self.parent_scope {
+                Some(parent) => parent.borrow_mut().set_slot(depth - 1, slot, val),
+                None => false,
+            };
+        }
+        if slot >= self.slots.len() {
+            self.slots.resize(slot + 1, None);
+        }
+        self.slots[slot] = Some(val);
+        true
+    }
 }
 
@@ -8,24 +8,29 @@ mod tests {
     fn frame_scope() {
         let global_scope: Rc<RefCell<Scope>> = Rc::new(RefCell::new(Scope::Block(ScopeStruct {
             play_object: HashMap::new(),
+            slots: vec![],
             parent_scope: None,
         })));
         let mut first_frame = Frame {
             parent_frame: None,
             scope: global_scope,
+            depth: 0,
+            max_depth: 1,
+            globals: Default::default(),
         };
         first_frame.set(String::from("a"), Rc::new(ValType::Number(1)), true);
 
         // 创建新作用域
         let b_scope = Rc::new(RefCell::new(Scope::Block(ScopeStruct {
             play_object: HashMap::new(),
+            slots: vec![],
             parent_scope: None,
         })));
         first_frame.create_scope(b_scope);
 
         // 测试包含
         let has = first_frame.contains_key(&String::from("a"));
-        assert_eq!(has, true);
+        assert!(has);
 
         // 测试获取值
         let get_res = first_frame.get(&String::from("a")).unwrap();
@@ -51,4 +56,63 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    // 局部变量按槽位存取, depth是往外的层数
+    fn frame_slot() {
+        let global_scope: Rc<RefCell<Scope>> =
+            Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None))));
+        let mut first_frame = Frame {
+            parent_frame: None,
+            scope: global_scope,
+            depth: 0,
+            max_depth: 1,
+            globals: Default::default(),
+        };
+        first_frame.create_scope(Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None)))));
+        first_frame.set_slot(0, 1, Rc::new(ValType::Number(1)));
+        assert_eq!(first_frame.get_slot(0, 0), None);
+        assert_eq!(
+            first_frame.get_slot(0, 1),
+            Some(Rc::new(ValType::Number(1)))
+        );
+
+        first_frame.create_scope(Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None)))));
+        first_frame.set_slot(1, 1, Rc::new(ValType::Number(2)));
+        assert_eq!(
+            first_frame.get_slot(1, 1),
+            Some(Rc::new(ValType::Number(2)))
+        );
+        assert_eq!(first_frame.get_slot(0, 1), None);
+        // 超出作用域链
+        assert_eq!(first_frame.get_slot(3, 0), None);
+        assert!(!first_frame.set_slot(3, 0, Rc::new(ValType::Unit)));
+    }
+
+    #[test]
+    // 调用函数的frame和全局frame共用全局变量
+    fn frame_global() {
+        let global_scope: Rc<RefCell<Scope>> =
+            Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None))));
+        let mut first_frame = Frame {
+            parent_frame: None,
+            scope: global_scope.clone(),
+            depth: 0,
+            max_depth: 1,
+            globals: Default::default(),
+        };
+        first_frame.set_global(1, Rc::new(ValType::Number(1)));
+        assert_eq!(first_frame.get_global(0), None);
+
+        let mut call_frame = Frame {
+            parent_frame: None,
+            scope: global_scope,
+            depth: 1,
+            max_depth: 1,
+            globals: first_frame.globals.clone(),
+        };
+        assert_eq!(call_frame.get_global(1), Some(Rc::new(ValType::Number(1))));
+        call_frame.set_global(1, Rc::new(ValType::Number(2)));
+        assert_eq!(first_frame.get_global(1), Some(Rc::new(ValType::Number(2))));
+    }
 }
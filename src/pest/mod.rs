@@ -1,18 +1,6 @@
-use pest::Parser;
-use pest_derive::Parser;
+pub mod parse_ast;
 
-#[allow(implied_bounds_entailment)]
-mod parse_ast;
-
-mod frame;
-mod slick_script;
-
-
-
-#[cfg(test)]
-mod tests {
-    use std::{env, fs};
-
-    use super::*;
-
-}
+pub mod frame;
+pub mod resolve;
+pub mod slick_script;
+pub mod type_check;
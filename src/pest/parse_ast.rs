@@ -1,11 +1,13 @@
+use pest::error::{ErrorVariant, LineColLocation};
 use pest::iterators::{Pair, Pairs};
 use pest::pratt_parser::PrattParser;
 use pest::Parser;
-use std::io::{self, BufRead};
-use std::{env, fs, vec};
+use std::{env, fmt, fs, rc::Rc, vec};
 
-use super::frame::ValType;
+use crate::util::{Diagnostic, SimpleError};
 
+#[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests;
 
 #[derive(pest_derive::Parser)]
@@ -19,28 +21,41 @@ lazy_static::lazy_static! {
 
         // Precedence is defined lowest to highest
         PrattParser::new()
+            .op(Op::infix(or, Left))
+            .op(Op::infix(and, Left))
+            .op(Op::infix(eq, Left) | Op::infix(not_eq, Left))
+            .op(Op::infix(large, Left)
+                | Op::infix(less, Left)
+                | Op::infix(large_eq, Left)
+                | Op::infix(less_eq, Left))
             // Addition and subtract have equal precedence
             .op(Op::infix(add, Left) | Op::infix(subtract, Left))
-            .op(Op::infix(multiply, Left) | Op::infix(divide, Left))
-            // .op(Op::prefix(unary_minus))
+            .op(Op::infix(multiply, Left) | Op::infix(divide, Left) | Op::infix(modulo, Left))
+            .op(Op::prefix(unary_minus) | Op::prefix(not))
+            // 调用, 下标和字段比一元运算优先, -f(1) 是 -(f(1))
+            .op(Op::postfix(call_args) | Op::postfix(index) | Op::postfix(field))
     };
 }
 
-// #[derive(Debug)]
-// pub enum Expr {
-//     Integer(i32),
-//     UnaryMinus(Box<Expr>),
-//     BinOp {
-//         lhs: Box<Expr>,
-//         op: Op,
-//         rhs: Box<Expr>,
-//     },
-// }
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DeclareType {
     Int,
+    // i64
+    Long,
+    // f64
+    Float,
     String,
+    Bool,
+    // 数组, 元素的类型
+    Array(Box<DeclareType>),
+    Map {
+        key: Box<DeclareType>,
+        value: Box<DeclareType>,
+    },
+    // 结构体或者枚举的名字, 具体是哪种由声明决定
+    Named(String),
+    // void函数调用的结果, 只在类型检查时出现
+    Void,
     FnType {
         return_type: Option<Box<DeclareType>>,
         // 这里只能是declare_stat, 比如int , fn int ()
@@ -48,58 +63,186 @@ pub enum DeclareType {
     },
 }
 
+/**
+ * 按源码里的写法显示类型, 比如 fn int (int, string)
+ */
+impl fmt::Display for DeclareType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeclareType::Int => write!(f, "int"),
+            DeclareType::Long => write!(f, "long"),
+            DeclareType::Float => write!(f, "float"),
+            DeclareType::String => write!(f, "string"),
+            DeclareType::Bool => write!(f, "bool"),
+            DeclareType::Array(item) => write!(f, "{}[]", item),
+            DeclareType::Map { key, value } => write!(f, "map<{}, {}>", key, value),
+            DeclareType::Named(name) => write!(f, "{}", name),
+            DeclareType::Void => write!(f, "void"),
+            DeclareType::FnType {
+                return_type,
+                argu_list,
+            } => {
+                match return_type {
+                    Some(return_type) => write!(f, "fn {} (", return_type)?,
+                    None => write!(f, "fn void (")?,
+                }
+                for (index, argu) in argu_list.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argu)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/**
+ * 变量在运行时的位置, 解析完是Unresolved, 由resolve填写
+ * 局部变量沿父作用域往外走层数那么多层, 再按槽位取, 全局变量按槽位存在Frame的globals里
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Address {
+    #[default]
+    Unresolved,
+    // 全局变量表里的第slot个变量
+    Global {
+        slot: usize,
+    },
+    // 从当前作用域往外数depth层, 第slot个变量
+    Local {
+        depth: usize,
+        slot: usize,
+    },
+}
+
+/**
+ * 节点在源码里的位置, 字节偏移 [start, end)
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /**
+     * 起始位置对应的行和列, 都从1开始, 列按字符计算
+     */
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    // 从开始到另一个span的结束
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        Span::new(span.start(), span.end())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum AstNodeType {
     Statement {
         child: Box<Vec<AstNodeType>>,
+        span: Span,
     },
-    IntLiteral(i32),
-    AdditiveExp {
-        // mult
-        child: Vec<AstNodeType>,
+    IntLiteral(i32, Span),
+    LongLiteral(i64, Span),
+    FloatLiteral(f64, Span),
+    BoolLiteral(bool, Span),
+    StringLiteral(String, Span),
+    // 二元运算, op: + - * / %
+    BinaryExp {
+        op: String,
+        left: Box<AstNodeType>,
+        right: Box<AstNodeType>,
+        span: Span,
     },
-    MulitiExp {
-        // atom
-        child: Vec<AstNodeType>,
+    // 一元运算, op: - !
+    UnaryExp {
+        op: String,
+        exp: Box<AstNodeType>,
+        span: Span,
     },
+    // 比较运算, judge: == != > < >= <=
     JudgeExp {
-        // mult
         left: Box<AstNodeType>,
         right: Box<AstNodeType>,
         judge: String,
+        span: Span,
+    },
+    // 逻辑运算, op: && ||, 短路求值
+    LogicExp {
+        op: String,
+        left: Box<AstNodeType>,
+        right: Box<AstNodeType>,
+        span: Span,
     },
     IfStatement {
         judge_stat: Box<AstNodeType>,
         // blocks
         if_stat: Box<AstNodeType>,
         else_stat: Option<Box<AstNodeType>>,
+        span: Span,
     },
     Block {
         statements: Option<Vec<AstNodeType>>,
+        span: Span,
     },
     Identifier {
         ident: String,
+        address: Address,
+        span: Span,
     },
     AssignmentStatement {
         // I属性, 从申明中找到
         ident: String,
+        address: Address,
         // S属性, 下级节点推导, check
         additive: Box<AstNodeType>,
+        span: Span,
     },
     // 函数体定义
     FnDeclaration {
         return_type: Option<Box<DeclareType>>,
         identifier: String,
+        address: Address,
         // Declaration, 比如int a, fn int () b
-        argu_list: Vec<AstNodeType>,
+        // 参数和函数体在闭包里共用, 调用时不用复制
+        argu_list: Rc<Vec<AstNodeType>>,
         // 这里只能是AstNodeType::Block
-        block: Box<AstNodeType>,
+        block: Rc<AstNodeType>,
+        span: Span,
+    },
+    // 匿名函数表达式, 和FnDeclaration一样生成Closure, 但不绑定名字
+    FnExpression {
+        return_type: Option<Box<DeclareType>>,
+        // Declaration
+        argu_list: Rc<Vec<AstNodeType>>,
+        // 这里只能是AstNodeType::Block
+        block: Rc<AstNodeType>,
+        span: Span,
     },
     // 函数调用
     FnCall {
-        // AstNodeType::Identifier
+        // 被调用的表达式, 一般是Identifier, 也可以是FnCall等任意表达式
         identifier: Box<AstNodeType>,
         argu_list: Vec<AstNodeType>,
+        span: Span,
     },
     // 普通声明
     Declaration {
@@ -107,310 +250,718 @@ pub enum AstNodeType {
         declare_type: DeclareType,
         // I属性, 根节点继承下来
         identifier: String,
+        address: Address,
         // S属性, 下级节点推导 check
         additive: Option<Box<AstNodeType>>,
+        span: Span,
+    },
+    // 数组字面量 [1, 2, 3]
+    ArrayLiteral {
+        elements: Vec<AstNodeType>,
+        span: Span,
+    },
+    // map字面量 {"a": 1}, 按书写顺序保存
+    MapLiteral {
+        entries: Vec<(AstNodeType, AstNodeType)>,
+        span: Span,
+    },
+    // 下标取值 target[index], 数组按位置, map按key
+    IndexExp {
+        target: Box<AstNodeType>,
+        index: Box<AstNodeType>,
+        span: Span,
+    },
+    // 下标赋值 target[index] = additive
+    IndexAssignment {
+        target: Box<AstNodeType>,
+        index: Box<AstNodeType>,
+        additive: Box<AstNodeType>,
+        span: Span,
+    },
+    // struct Point { int x; int y; }
+    StructDeclaration {
+        name: String,
+        // 字段的类型和名字, 按声明顺序
+        fields: Vec<(DeclareType, String)>,
+        span: Span,
+    },
+    // Point { x: 1, y: 2 }
+    StructLiteral {
+        name: String,
+        // 字段名, 值, 字段的位置
+        fields: Vec<(String, AstNodeType, Span)>,
+        span: Span,
+    },
+    // 字段取值 target.field
+    FieldExp {
+        target: Box<AstNodeType>,
+        field: String,
+        span: Span,
+    },
+    // 字段赋值 target.field = additive
+    FieldAssignment {
+        target: Box<AstNodeType>,
+        field: String,
+        additive: Box<AstNodeType>,
+        span: Span,
+    },
+    // enum Shape { Circle(int), Rect(int, int) }
+    EnumDeclaration {
+        name: String,
+        // 成员名和成员带的值的类型
+        variants: Vec<(String, Vec<DeclareType>)>,
+        span: Span,
+    },
+    // Shape::Circle(1)
+    EnumLiteral {
+        name: String,
+        variant: String,
+        args: Vec<AstNodeType>,
+        span: Span,
+    },
+    // match (target) { Circle(r) => {} _ => {} }
+    MatchStatement {
+        target: Box<AstNodeType>,
+        arms: Vec<MatchArm>,
+        span: Span,
+    },
+    // var/let声明, 类型由类型检查从初始值推导
+    VarDeclaration {
+        identifier: String,
+        address: Address,
+        additive: Box<AstNodeType>,
+        span: Span,
+    },
+    // while (judge_stat) block
+    WhileStatement {
+        judge_stat: Box<AstNodeType>,
+        // AstNodeType::Block
+        block: Box<AstNodeType>,
+        span: Span,
+    },
+    // for (init; judge_stat; step) block
+    ForStatement {
+        // Declaration, AssignmentStatement 或者表达式
+        init: Option<Box<AstNodeType>>,
+        judge_stat: Option<Box<AstNodeType>>,
+        step: Option<Box<AstNodeType>>,
+        // AstNodeType::Block
+        block: Box<AstNodeType>,
+        span: Span,
+    },
+    BreakStatement {
+        span: Span,
+    },
+    ContinueStatement {
+        span: Span,
     },
     ReturnExp {
-        // return_stat = {return ~ additive | judge_stat | fn_declare }
+        // return_stat = {return ~ additive? }
         exp: Option<Box<AstNodeType>>,
+        span: Span,
     },
 }
 
-fn parse_add(pair: Pair<Rule>) -> AstNodeType {
-    let mut ast: Vec<AstNodeType> = vec![];
+/**
+ * match的一个分支, Circle(r) => { }
+ * variant为None时是 _, 匹配剩下的所有成员
+ */
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub variant: Option<String>,
+    // 按顺序绑定成员带的值
+    pub bindings: Vec<String>,
+    // AstNodeType::Block
+    pub block: AstNodeType,
+    pub span: Span,
+}
 
-    // 将mul拿出来相加
-    let muls = pair.into_inner();
-    for mul in muls {
-        match mul.as_rule() {
-            Rule::multi => {
-                let multi_node = parse_mul(mul);
-                ast.push(multi_node);
-            }
-            Rule::add => {
-                // nothing
-            }
-            rule => {
-                unreachable!("Expr::parse expected multi operation, found {:?}", rule)
-            }
+impl AstNodeType {
+    pub fn span(&self) -> Span {
+        match self {
+            AstNodeType::IntLiteral(_, span)
+            | AstNodeType::LongLiteral(_, span)
+            | AstNodeType::FloatLiteral(_, span)
+            | AstNodeType::BoolLiteral(_, span)
+            | AstNodeType::StringLiteral(_, span)
+            | AstNodeType::Statement { span, .. }
+            | AstNodeType::BinaryExp { span, .. }
+            | AstNodeType::UnaryExp { span, .. }
+            | AstNodeType::JudgeExp { span, .. }
+            | AstNodeType::LogicExp { span, .. }
+            | AstNodeType::IfStatement { span, .. }
+            | AstNodeType::Block { span, .. }
+            | AstNodeType::Identifier { span, .. }
+            | AstNodeType::AssignmentStatement { span, .. }
+            | AstNodeType::FnDeclaration { span, .. }
+            | AstNodeType::FnExpression { span, .. }
+            | AstNodeType::FnCall { span, .. }
+            | AstNodeType::Declaration { span, .. }
+            | AstNodeType::VarDeclaration { span, .. }
+            | AstNodeType::ArrayLiteral { span, .. }
+            | AstNodeType::MapLiteral { span, .. }
+            | AstNodeType::StructDeclaration { span, .. }
+            | AstNodeType::StructLiteral { span, .. }
+            | AstNodeType::FieldExp { span, .. }
+            | AstNodeType::FieldAssignment { span, .. }
+            | AstNodeType::EnumDeclaration { span, .. }
+            | AstNodeType::EnumLiteral { span, .. }
+            | AstNodeType::MatchStatement { span, .. }
+            | AstNodeType::IndexExp { span, .. }
+            | AstNodeType::IndexAssignment { span, .. }
+            | AstNodeType::WhileStatement { span, .. }
+            | AstNodeType::ForStatement { span, .. }
+            | AstNodeType::BreakStatement { span }
+            | AstNodeType::ContinueStatement { span }
+            | AstNodeType::ReturnExp { span, .. } => *span,
         }
     }
+}
 
-    AstNodeType::AdditiveExp {
-        child: ast,
-    }
+/**
+ * 解析表达式, 按PRATT_PARSER定义的优先级生成
+ * BinaryExp/JudgeExp/LogicExp/UnaryExp/FnCall/IndexExp/FieldExp
+ */
+fn parse_add(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    PRATT_PARSER
+        .map_primary(parse_atom)
+        .map_prefix(|op, exp| {
+            let exp = exp?;
+            Ok(AstNodeType::UnaryExp {
+                op: op.as_str().into(),
+                span: Span::from(op.as_span()).to(exp.span()),
+                exp: Box::new(exp),
+            })
+        })
+        .map_postfix(|callee, op| match op.as_rule() {
+            Rule::index => parse_index(callee?, op),
+            Rule::field => parse_field(callee?, op),
+            _ => parse_fn_call(callee?, op),
+        })
+        .map_infix(|left, op, right| {
+            let (left, right) = (left?, right?);
+            let span = left.span().to(right.span());
+            Ok(match op.as_rule() {
+                Rule::eq
+                | Rule::not_eq
+                | Rule::large
+                | Rule::less
+                | Rule::large_eq
+                | Rule::less_eq => AstNodeType::JudgeExp {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    judge: op.as_str().into(),
+                    span,
+                },
+                Rule::and | Rule::or => AstNodeType::LogicExp {
+                    op: op.as_str().into(),
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    span,
+                },
+                _ => AstNodeType::BinaryExp {
+                    op: op.as_str().into(),
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    span,
+                },
+            })
+        })
+        .parse(pair.into_inner())
 }
 
-fn parse_atom(pair: Pair<Rule>) -> AstNodeType {
-    match pair.as_rule() {
+fn parse_atom(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
+    let atom = match pair.as_rule() {
         Rule::ident => AstNodeType::Identifier {
             ident: pair.as_str().into(),
+            address: Address::Unresolved,
+            span,
+        },
+        Rule::integer => match pair.as_str().parse() {
+            Ok(val) => AstNodeType::IntLiteral(val, span),
+            Err(_) => {
+                return Err(literal_error(
+                    &pair,
+                    format!(
+                        "{} 超出 int 的范围, long 要写成 {}L",
+                        pair.as_str(),
+                        pair.as_str()
+                    ),
+                ))
+            }
         },
-        Rule::integer => AstNodeType::IntLiteral(pair.as_str().parse().unwrap()),
-        Rule::fn_call => parse_fn_call(pair),
+        Rule::long_l => {
+            let digits = pair.as_str().trim_end_matches('L');
+            match digits.parse() {
+                Ok(val) => AstNodeType::LongLiteral(val, span),
+                Err(_) => {
+                    return Err(literal_error(
+                        &pair,
+                        format!("{} 超出 long 的范围", pair.as_str()),
+                    ))
+                }
+            }
+        }
+        // 按语法规则总能解析成f64, 太大时得到inf, 要当成超出范围报错
+        Rule::float_l => match pair.as_str().parse::<f64>() {
+            Ok(val) if val.is_finite() => AstNodeType::FloatLiteral(val, span),
+            _ => {
+                return Err(literal_error(
+                    &pair,
+                    format!("{} 超出 float 的范围", pair.as_str()),
+                ))
+            }
+        },
+        Rule::boolean => AstNodeType::BoolLiteral(pair.as_str() == "true", span),
+        Rule::string_l => AstNodeType::StringLiteral(unescape(pair.into_inner().as_str()), span),
+        Rule::fn_expr => parse_fn_expr(pair)?,
+        Rule::array_l => {
+            let mut elements = vec![];
+            for element in pair.into_inner() {
+                elements.push(parse_add(element)?);
+            }
+            AstNodeType::ArrayLiteral { elements, span }
+        }
+        Rule::map_l => {
+            let mut entries = vec![];
+            for entry in pair.into_inner() {
+                let mut entry_inner = entry.into_inner();
+                let key = parse_add(entry_inner.next().unwrap())?;
+                let value = parse_add(entry_inner.next().unwrap())?;
+                entries.push((key, value));
+            }
+            AstNodeType::MapLiteral { entries, span }
+        }
+        Rule::enum_l => {
+            let mut enum_inner = pair.into_inner();
+            let name = enum_inner.next().unwrap().as_str().into();
+            let variant = enum_inner.next().unwrap().as_str().into();
+            let mut args = vec![];
+            // 不带值的成员没有括号
+            if let Some(call_args) = enum_inner.next() {
+                for arg in call_args.into_inner() {
+                    args.push(parse_add(arg)?);
+                }
+            }
+            AstNodeType::EnumLiteral {
+                name,
+                variant,
+                args,
+                span,
+            }
+        }
+        Rule::struct_l => {
+            let mut struct_inner = pair.into_inner();
+            let name = struct_inner.next().unwrap().as_str().into();
+            let mut fields = vec![];
+            for field_init in struct_inner {
+                let field_span = Span::from(field_init.as_span());
+                let mut field_inner = field_init.into_inner();
+                let field = field_inner.next().unwrap().as_str().into();
+                let value = parse_add(field_inner.next().unwrap())?;
+                fields.push((field, value, field_span));
+            }
+            AstNodeType::StructLiteral { name, fields, span }
+        }
+        // 括号里的子表达式
+        Rule::additive => parse_add(pair)?,
         rule => {
             unreachable!("Expr::parse expected atom operation, found {:?}", rule);
         }
-
-    }
+    };
+    Ok(atom)
 }
-fn parse_mul(pair: Pair<Rule>) -> AstNodeType {
-    let mut ast: Vec<AstNodeType> = vec![];
-
-    let atoms = pair.into_inner();
 
-    for atom in atoms {
-        if atom.as_rule() != Rule::multiply {
-            ast.push(parse_atom(atom));
+/**
+ * 处理字符串里的转义字符
+ */
+fn unescape(raw: &str) -> String {
+    let mut res = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        // 语法里已经限定了转义字符的范围
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('r') => res.push('\r'),
+            Some(other) => res.push(other),
+            None => {}
         }
     }
-    AstNodeType::MulitiExp {
-        child: ast,
-    }
+    res
 }
 
-pub fn parse_simple(pairs: Pairs<Rule>) -> Vec<AstNodeType> {
+pub fn parse_simple(pairs: Pairs<Rule>) -> Result<Vec<AstNodeType>, SimpleError> {
     let mut ast: Vec<AstNodeType> = vec![];
 
     for pair in pairs {
-        match pair.as_rule() {
-            Rule::statement => {
-                let inner = pair.into_inner();
+        // 跳过最后的EOI
+        if pair.as_rule() != Rule::statement {
+            continue;
+        }
+        let span = Span::from(pair.as_span());
+        let inner = pair.into_inner();
 
-                let parsed_child = parse_stat(inner);
+        let parsed_child = parse_stat(inner)?;
 
-                let state_node = AstNodeType::Statement {
-                    child: Box::new(parsed_child),
-                };
-                ast.push(state_node);
-            }
-            _ => {
-                println!("{:?} 不是statement", pair.as_rule());
-            }
-        }
+        let state_node = AstNodeType::Statement {
+            child: Box::new(parsed_child),
+            span,
+        };
+        ast.push(state_node);
     }
-    ast
+    Ok(ast)
 }
 
 /**
- * 解析各种情况
+ * 解析各种情况, 解析不了的语法报错, 不能静默丢掉
  */
-fn parse(pair: Pair<Rule>) -> Option<AstNodeType> {
+fn parse(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
     match pair.as_rule() {
         Rule::assi_stat => {
             let mut child_inner = pair.into_inner();
             let ident = child_inner.next().unwrap();
+            // ident后面跟着下标或字段, 然后是=和表达式
+            let mut accessors: Vec<Pair<Rule>> = vec![];
+            while let Some(Rule::index | Rule::field) = child_inner.peek().map(|p| p.as_rule()) {
+                accessors.push(child_inner.next().unwrap());
+            }
             child_inner.next();
             // 加法
-            let additive = child_inner.next().unwrap();
-            let ass_node = AstNodeType::AssignmentStatement {
+            let additive = Box::new(parse_add(child_inner.next().unwrap())?);
+            let Some(last) = accessors.pop() else {
+                return Ok(AstNodeType::AssignmentStatement {
+                    ident: ident.as_str().into(),
+                    address: Address::Unresolved,
+                    additive,
+                    span,
+                });
+            };
+            // a[i].x = v, 前面的取值, 最后一个赋值
+            let mut target = AstNodeType::Identifier {
                 ident: ident.as_str().into(),
-                additive: Box::new(parse_add(additive)),
+                address: Address::Unresolved,
+                span: Span::from(ident.as_span()),
             };
-            return Some(ass_node);
+            for accessor in accessors {
+                target = match accessor.as_rule() {
+                    Rule::index => parse_index(target, accessor)?,
+                    _ => parse_field(target, accessor)?,
+                };
+            }
+            let target = Box::new(target);
+            let last_inner = last.clone().into_inner().next().unwrap();
+            match last.as_rule() {
+                Rule::index => Ok(AstNodeType::IndexAssignment {
+                    target,
+                    index: Box::new(parse_add(last_inner)?),
+                    additive,
+                    span,
+                }),
+                _ => Ok(AstNodeType::FieldAssignment {
+                    target,
+                    field: last_inner.as_str().into(),
+                    additive,
+                    span,
+                }),
+            }
         }
         Rule::declare_stat => {
             // 判断声明的类型, 生成Declaration节点
+            // int, string, bool, fn类型都一样处理
             let mut child_inner = pair.into_inner();
-            let declare_type = child_inner.next().unwrap();
-            let mut type_inner = declare_type.clone().into_inner();
-            let some_type = type_inner.next().unwrap();
-
-            match some_type.as_rule() {
-                Rule::int => {
-                    let ident = child_inner.next().unwrap();
-                    child_inner.next();
-                    // 加法
-                    let expr = child_inner.next();
-                    let declare_node = AstNodeType::Declaration {
-                        declare_type: DeclareType::Int,
-                        identifier: ident.as_str().into(),
-                        additive: match expr {
-                            Some(exp) => Some(Box::new(parse_add(exp))),
-                            None => None,
-                        },
-                    };
-                    return Some(declare_node);
-                }
-                Rule::fn_type => {
-                    let fn_type_node = parse_type(declare_type);
-                    let ident = child_inner.next().unwrap();
-                    child_inner.next();
-                    // 表达式
-                    let expr = child_inner.next();
-                    match fn_type_node {
-                        Some(fn_typed) => {
-                            let fn_declare_node = AstNodeType::Declaration {
-                                declare_type: fn_typed,
-                                identifier: ident.as_str().into(),
-                                additive: match expr {
-                                    Some(exp) => Some(Box::new(parse_add(exp))),
-                                    None => None,
-                                },
-                            };
-                            return Some(fn_declare_node);
-                        }
-                        None => return None,
+            let declare_type = parse_type(child_inner.next().unwrap())?;
+            let ident = child_inner.next().unwrap();
+            child_inner.next();
+            // 初始值表达式
+            let expr = child_inner.next();
+            let declare_node = AstNodeType::Declaration {
+                declare_type,
+                identifier: ident.as_str().into(),
+                address: Address::Unresolved,
+                additive: match expr {
+                    Some(exp) => Some(Box::new(parse_add(exp)?)),
+                    None => None,
+                },
+                span,
+            };
+            Ok(declare_node)
+        }
+        Rule::var_stat => {
+            let mut child_inner = pair.into_inner();
+            // 跳过var
+            child_inner.next();
+            let ident = child_inner.next().unwrap();
+            child_inner.next();
+            let additive = child_inner.next().unwrap();
+            Ok(AstNodeType::VarDeclaration {
+                identifier: ident.as_str().into(),
+                address: Address::Unresolved,
+                additive: Box::new(parse_add(additive)?),
+                span,
+            })
+        }
+        Rule::additive => parse_add(pair),
+        Rule::if_statement => parse_if(pair),
+        Rule::block => parse_block(pair),
+        Rule::while_statement => parse_while(pair),
+        Rule::for_statement => parse_for(pair),
+        Rule::break_stat => Ok(AstNodeType::BreakStatement { span }),
+        Rule::continue_stat => Ok(AstNodeType::ContinueStatement { span }),
+        Rule::fn_declare => parse_fn_decare(pair),
+        Rule::struct_declare => {
+            let mut struct_inner = pair.into_inner();
+            // 跳过struct
+            struct_inner.next();
+            let name = struct_inner.next().unwrap().as_str().into();
+            let mut fields = vec![];
+            for struct_field in struct_inner {
+                let mut field_inner = struct_field.into_inner();
+                let declare_type = parse_type(field_inner.next().unwrap())?;
+                fields.push((declare_type, field_inner.next().unwrap().as_str().into()));
+            }
+            Ok(AstNodeType::StructDeclaration { name, fields, span })
+        }
+        Rule::enum_declare => {
+            let mut enum_inner = pair.into_inner();
+            // 跳过enum
+            enum_inner.next();
+            let name = enum_inner.next().unwrap().as_str().into();
+            let mut variants = vec![];
+            for variant in enum_inner {
+                let mut variant_inner = variant.into_inner();
+                let variant_name = variant_inner.next().unwrap().as_str().into();
+                let mut types = vec![];
+                if let Some(type_list) = variant_inner.next() {
+                    for item in type_list.into_inner() {
+                        types.push(parse_type(item)?);
                     }
                 }
-                _ => {
-                    return None;
-                }
+                variants.push((variant_name, types));
             }
+            Ok(AstNodeType::EnumDeclaration {
+                name,
+                variants,
+                span,
+            })
         }
-        Rule::additive => return Some(parse_add(pair)),
-        Rule::judge_stat => return Some(parse_judge(pair)),
-        Rule::if_statement => return Some(parse_if(pair)),
-        Rule::fn_declare => return Some(parse_fn_decare(pair)),
+        Rule::match_statement => parse_match(pair),
         Rule::return_stat => {
             let mut child_inner = pair.into_inner();
             child_inner.next();
-            // 找到return后面的表达式
-            // additive | judge_stat | fn_declare
-            let expr = parse(child_inner.next().unwrap());
+            // 找到return后面的表达式, 可以没有
+            let expr = child_inner.next().map(parse).transpose()?;
             let return_node = AstNodeType::ReturnExp {
-                exp: match expr {
-                    Some(node) => Some(Box::new(node)),
-                    None => None,
-                },
+                exp: expr.map(Box::new),
+                span,
             };
-            return Some(return_node);
-        }
-        r => {
-            //nothing
-            None
+            Ok(return_node)
         }
+        r => Err(SimpleError::Ast(format!(
+            "不支持的语法 {:?}: {}",
+            r,
+            pair.as_str()
+        ))),
     }
 }
 
-fn parse_type_or_void(pair: Pair<Rule>) -> Option<Box<DeclareType>> {
+fn parse_type_or_void(pair: Pair<Rule>) -> Result<Option<Box<DeclareType>>, SimpleError> {
     let type_or_void_inner = pair.into_inner().peek().unwrap();
     let what_type = match type_or_void_inner.as_rule() {
         Rule::void => None,
-        _ => match parse_type(type_or_void_inner) {
-            Some(node) => Some(Box::new(node)),
-            None => None,
-        },
+        _ => Some(Box::new(parse_type(type_or_void_inner)?)),
     };
-    return what_type;
+    Ok(what_type)
 }
 
 /**
  * 解析函数声明
  */
-fn parse_fn_decare(pair: Pair<Rule>) -> AstNodeType {
+fn parse_fn_decare(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
     let mut child_inner = pair.into_inner();
 
-    let return_type = parse_type_or_void(child_inner.next().unwrap());
+    let return_type = parse_type_or_void(child_inner.next().unwrap())?;
 
     let identifier: String = child_inner.next().unwrap().as_str().into();
 
-    let argu_list = child_inner.next().unwrap();
-    let mut argu_list_inner = argu_list.into_inner();
-    let mut params = Vec::new();
-    while let Some(declare_stat) = argu_list_inner.next() {
-        params.push(parse(declare_stat).unwrap());
-    }
+    let params = parse_argu_list(child_inner.next().unwrap())?;
 
     let body = child_inner.next().unwrap();
-    let body_node = parse_block(body);
+    let body_node = parse_block(body)?;
     let fn_declare_node = AstNodeType::FnDeclaration {
         return_type,
         identifier,
-        argu_list: params,
-        block: Box::new(body_node),
+        address: Address::Unresolved,
+        argu_list: Rc::new(params),
+        block: Rc::new(body_node),
+        span,
     };
 
-    fn_declare_node
+    Ok(fn_declare_node)
 }
 
-fn parse_fn_call(pair: Pair<Rule>) -> AstNodeType {
+/**
+ * 解析匿名函数, 和函数声明一样, 只是没有名字
+ */
+fn parse_fn_expr(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
     let mut child_inner = pair.into_inner();
-    let identifier: String = child_inner.next().unwrap().as_str().into();
+    // 跳过function
+    child_inner.next();
+
+    let return_type = parse_type_or_void(child_inner.next().unwrap())?;
+    let argu_list = parse_argu_list(child_inner.next().unwrap())?;
+    let block = parse_block(child_inner.next().unwrap())?;
+    Ok(AstNodeType::FnExpression {
+        return_type,
+        argu_list: Rc::new(argu_list),
+        block: Rc::new(block),
+        span,
+    })
+}
+
+/**
+ * 解析形参列表, 每个形参都是Declaration
+ */
+fn parse_argu_list(pair: Pair<Rule>) -> Result<Vec<AstNodeType>, SimpleError> {
+    let mut params = Vec::new();
+    for declare_stat in pair.into_inner() {
+        params.push(parse(declare_stat)?);
+    }
+    Ok(params)
+}
+
+/**
+ * 解析后缀调用, callee可以是任意表达式
+ */
+fn parse_fn_call(callee: AstNodeType, call_args: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = callee.span().to(Span::from(call_args.as_span()));
     let mut argu_list = vec![];
 
-    while let Some(argu) = child_inner.next() {
-        argu_list.push(parse_add(argu));
-    };
-    AstNodeType::FnCall { identifier: Box::new(AstNodeType::Identifier { ident: identifier }), argu_list, }
-    
+    for argu in call_args.into_inner() {
+        argu_list.push(parse_add(argu)?);
+    }
+    Ok(AstNodeType::FnCall {
+        identifier: Box::new(callee),
+        argu_list,
+        span,
+    })
+}
+
+/**
+ * 解析下标 target[index]
+ */
+fn parse_index(target: AstNodeType, index: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = target.span().to(Span::from(index.as_span()));
+    let index = parse_add(index.into_inner().next().unwrap())?;
+    Ok(AstNodeType::IndexExp {
+        target: Box::new(target),
+        index: Box::new(index),
+        span,
+    })
+}
+
+/**
+ * 解析字段 target.field
+ */
+fn parse_field(target: AstNodeType, field: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = target.span().to(Span::from(field.as_span()));
+    Ok(AstNodeType::FieldExp {
+        target: Box::new(target),
+        field: field.into_inner().next().unwrap().as_str().into(),
+        span,
+    })
 }
 
 /**
  * 解析各种type
- * 生成AstNodeType
+ * 生成DeclareType, 不认识的类型报错
  */
-fn parse_type(pair: Pair<Rule>) -> Option<DeclareType> {
+fn parse_type(pair: Pair<Rule>) -> Result<DeclareType, SimpleError> {
     let mut child_inner = pair.into_inner();
-    let type_node = child_inner.next().unwrap();
+    let mut declare_type = parse_base_type(child_inner.next().unwrap())?;
+    // 每个[]包一层数组
+    for _ in child_inner {
+        declare_type = DeclareType::Array(Box::new(declare_type));
+    }
+    Ok(declare_type)
+}
+
+fn parse_base_type(type_node: Pair<Rule>) -> Result<DeclareType, SimpleError> {
     match type_node.as_rule() {
         Rule::int => {
             let type_node = DeclareType::Int;
-            Some(type_node)
+            Ok(type_node)
+        }
+        Rule::long => Ok(DeclareType::Long),
+        Rule::float => Ok(DeclareType::Float),
+        Rule::str => Ok(DeclareType::String),
+        Rule::bool => Ok(DeclareType::Bool),
+        // 结构体或者枚举的名字, 是否声明过由类型检查判断
+        Rule::ident => Ok(DeclareType::Named(type_node.as_str().into())),
+        Rule::map_type => {
+            let mut map_inner = type_node.into_inner();
+            // 跳过map
+            map_inner.next();
+            let key = parse_type(map_inner.next().unwrap())?;
+            let value = parse_type(map_inner.next().unwrap())?;
+            Ok(DeclareType::Map {
+                key: Box::new(key),
+                value: Box::new(value),
+            })
         }
         Rule::fn_type => {
             let mut fn_inner = type_node.into_inner();
             // 跳过functioon
             fn_inner.next();
             let type_or_void = fn_inner.next().unwrap();
-            let return_type = parse_type_or_void(type_or_void);
+            let return_type = parse_type_or_void(type_or_void)?;
             // parse type_or_void;
             let type_list = fn_inner.next().unwrap();
             let mut list_vec = vec![];
             for item in type_list.into_inner() {
-                match parse_type(item) {
-                    Some(node) => {
-                        list_vec.push(node);
-                    }
-                    None => {
-                        panic!("item节点不是type{:?}", fn_inner);
-                    }
-                }
+                list_vec.push(parse_type(item)?);
             }
 
             let fn_typed = DeclareType::FnType {
-                return_type: return_type,
+                return_type,
                 argu_list: Box::new(list_vec),
             };
-            Some(fn_typed)
+            Ok(fn_typed)
         }
-        _ => None,
+        _ => Err(SimpleError::Ast(format!(
+            "不支持的类型 {}",
+            type_node.as_str()
+        ))),
     }
 }
 
-fn parse_stat(childs: Pairs<Rule>) -> Vec<AstNodeType> {
+fn parse_stat(childs: Pairs<Rule>) -> Result<Vec<AstNodeType>, SimpleError> {
     let mut ast: Vec<AstNodeType> = vec![];
 
     for child in childs {
-        match parse(child) {
-            Some(node) => ast.push(node),
-            None => {
-                // nothing
-            }
-        }
+        ast.push(parse(child)?);
     }
 
-    ast
+    Ok(ast)
 }
 
-fn parse_judge(pair: Pair<Rule>) -> AstNodeType {
-    let mut child_inner = pair.into_inner();
-    // 找到左边和右边
-    let left = child_inner.next().unwrap();
-    let judge = child_inner.next().unwrap();
-    let right = child_inner.next().unwrap();
-    AstNodeType::JudgeExp {
-        left: Box::new(parse_add(left)),
-        right: Box::new(parse_add(right)),
-        judge: judge.as_str().into(),
-    }
-}
-
-fn parse_block(pair: Pair<Rule>) -> AstNodeType {
+fn parse_block(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
     let blocks_inner = pair.into_inner();
     // 找到左边和右边
     if blocks_inner.len() == 0 {
-        return AstNodeType::Block { statements: None };
+        return Ok(AstNodeType::Block {
+            statements: None,
+            span,
+        });
     }
 
     let mut ast: Vec<AstNodeType> = vec![];
@@ -418,12 +969,14 @@ fn parse_block(pair: Pair<Rule>) -> AstNodeType {
     for block in blocks_inner.clone() {
         match block.as_rule() {
             Rule::statement => {
+                let statement_span = Span::from(block.as_span());
                 let inner = block.into_inner();
 
-                let parsed_child = parse_stat(inner);
+                let parsed_child = parse_stat(inner)?;
 
                 let state_node = AstNodeType::Statement {
                     child: Box::new(parsed_child),
+                    span: statement_span,
                 };
                 ast.push(state_node);
             }
@@ -431,79 +984,232 @@ fn parse_block(pair: Pair<Rule>) -> AstNodeType {
         }
     }
 
-    AstNodeType::Block {
+    Ok(AstNodeType::Block {
         statements: Some(ast),
-    }
+        span,
+    })
 }
 
-fn parse_if(pair: Pair<Rule>) -> AstNodeType {
+fn parse_if(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
     let mut if_inner = pair.into_inner();
     let inner_len = if_inner.len();
     if_inner.next();
     let judge_stat = if_inner.next().unwrap();
     let if_block = if_inner.next().unwrap();
 
-    if (inner_len == 3) {
-        return AstNodeType::IfStatement {
-            judge_stat: Box::new(parse_judge(judge_stat)),
-            if_stat: Box::new(parse_block(if_block)),
+    if inner_len == 3 {
+        return Ok(AstNodeType::IfStatement {
+            judge_stat: Box::new(parse_add(judge_stat)?),
+            if_stat: Box::new(parse_block(if_block)?),
             else_stat: None,
-        };
+            span,
+        });
     }
 
     // 跳过else
     if_inner.next();
     let else_block = if_inner.next().unwrap();
-    let else_node = Some(Box::new(parse_block(else_block)));
+    let else_node = Some(Box::new(parse_block(else_block)?));
 
-    AstNodeType::IfStatement {
-        judge_stat: Box::new(parse_judge(judge_stat)),
-        if_stat: Box::new(parse_block(if_block)),
+    Ok(AstNodeType::IfStatement {
+        judge_stat: Box::new(parse_add(judge_stat)?),
+        if_stat: Box::new(parse_block(if_block)?),
         else_stat: else_node,
+        span,
+    })
+}
+
+fn parse_match(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
+    let mut match_inner = pair.into_inner();
+    // 跳过match
+    match_inner.next();
+    let target = parse_add(match_inner.next().unwrap())?;
+    let mut arms = vec![];
+    for arm in match_inner {
+        let arm_span = Span::from(arm.as_span());
+        let mut arm_inner = arm.into_inner();
+        let pattern = arm_inner.next().unwrap();
+        let variant = match pattern.as_rule() {
+            Rule::wildcard => None,
+            _ => Some(pattern.as_str().to_string()),
+        };
+        let mut bindings = vec![];
+        if let Some(Rule::bindings) = arm_inner.peek().map(|p| p.as_rule()) {
+            for binding in arm_inner.next().unwrap().into_inner() {
+                bindings.push(binding.as_str().to_string());
+            }
+        }
+        let block = parse_block(arm_inner.next().unwrap())?;
+        arms.push(MatchArm {
+            variant,
+            bindings,
+            block,
+            span: arm_span,
+        });
     }
+    Ok(AstNodeType::MatchStatement {
+        target: Box::new(target),
+        arms,
+        span,
+    })
 }
 
-pub fn parse_code(code: String) -> Vec<AstNodeType> {
+fn parse_while(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
+    let mut while_inner = pair.into_inner();
+    // 跳过while
+    while_inner.next();
+    let judge_stat = while_inner.next().unwrap();
+    let block = while_inner.next().unwrap();
+
+    Ok(AstNodeType::WhileStatement {
+        judge_stat: Box::new(parse_add(judge_stat)?),
+        block: Box::new(parse_block(block)?),
+        span,
+    })
+}
+
+fn parse_for(pair: Pair<Rule>) -> Result<AstNodeType, SimpleError> {
+    let span = Span::from(pair.as_span());
+    let mut for_inner = pair.into_inner();
+    // 跳过for
+    for_inner.next();
+    // for_init, for_cond, for_step 都可能是空的
+    let mut parse_part = || -> Result<Option<Box<AstNodeType>>, SimpleError> {
+        let part = for_inner.next().unwrap();
+        Ok(part
+            .into_inner()
+            .next()
+            .map(parse)
+            .transpose()?
+            .map(Box::new))
+    };
+    let init = parse_part()?;
+    let judge_stat = parse_part()?;
+    let step = parse_part()?;
+    let block = for_inner.next().unwrap();
+
+    Ok(AstNodeType::ForStatement {
+        init,
+        judge_stat,
+        step,
+        block: Box::new(parse_block(block)?),
+        span,
+    })
+}
+
+pub fn parse_code(code: String) -> Result<Vec<AstNodeType>, SimpleError> {
     match CalculatorParser::parse(Rule::equation, &code) {
-        Ok(pairs) => {
-            // println!("Parsed: {:#?}", pairs);
-            let res = parse_simple(pairs);
-            println!(
-                "Parsed: {:#?}",
-                // inner of expr
-                res // pairs
-            );
-            return res;
-        }
-        Err(e) => {
-            println!("Parse failed: {:?}", e);
-            return vec![];
-        }
+        Ok(pairs) => parse_simple(pairs),
+        Err(e) => Err(SimpleError::Parse(to_diagnostic(e))),
     }
 }
 
-pub fn parse_file() -> Vec<AstNodeType> {
+pub fn parse_file() -> Result<Vec<AstNodeType>, SimpleError> {
+    parse_code(read_file())
+}
+
+/**
+ * 读脚本文件的源码, 报错的时候用它把位置换算成行列
+ */
+pub fn read_file() -> String {
     let current_dir = env::current_dir().expect("无法获取当前工作目录");
     // 构建文件路径
     let file_path = current_dir.join("src/pest/calc.sc");
 
-    let unparsed_file = fs::read_to_string(file_path).expect("cannot read file");
+    fs::read_to_string(file_path).expect("cannot read file")
+}
 
-    // println!("{:?}", unparsed_file);
+/**
+ * 字面量不合法, 报在字面量的位置
+ */
+fn literal_error(pair: &Pair<Rule>, message: String) -> SimpleError {
+    let e =
+        pest::error::Error::new_from_span(ErrorVariant::CustomError { message }, pair.as_span());
+    SimpleError::Parse(to_diagnostic(e))
+}
 
-    match CalculatorParser::parse(Rule::equation, &unparsed_file) {
-        Ok(mut pairs) => {
-            let res = parse_simple(pairs);
-            println!(
-                "Parsed: {:#?}",
-                // inner of expr
-                res // pairs
-            );
-            return res;
-        }
-        Err(e) => {
-            println!("Parse failed: {:?}", e);
-            return vec![];
+/**
+ * pest的错误转成Diagnostic, 期望的规则换成可读的名字
+ */
+fn to_diagnostic(e: pest::error::Error<Rule>) -> Diagnostic {
+    let (line, column) = match e.line_col {
+        LineColLocation::Pos(pos) => pos,
+        LineColLocation::Span(start, _) => start,
+    };
+    let mut expected: Vec<String> = vec![];
+    let mut message = None;
+    match &e.variant {
+        ErrorVariant::ParsingError { positives, .. } => {
+            for rule in positives {
+                let name = rule_name(rule);
+                if !expected.contains(&name) {
+                    expected.push(name);
+                }
+            }
         }
+        ErrorVariant::CustomError { message: custom } => message = Some(custom.clone()),
     }
+    Diagnostic {
+        line,
+        column,
+        expected,
+        message,
+        source_line: e.line().trim_end_matches(['\r', '\n']).to_string(),
+    }
+}
+
+/**
+ * 语法规则的可读名字
+ */
+fn rule_name(rule: &Rule) -> String {
+    let name = match rule {
+        Rule::EOI => "文件结尾",
+        Rule::statement => "语句",
+        Rule::additive => "表达式",
+        Rule::block => "代码块",
+        Rule::typed | Rule::type_or_void => "类型",
+        Rule::ident => "标识符",
+        Rule::integer => "整数",
+        Rule::long_l => "long",
+        Rule::float_l => "浮点数",
+        Rule::string_l | Rule::string => "字符串",
+        Rule::boolean => "true/false",
+        Rule::declare_stat | Rule::var_stat => "变量声明",
+        Rule::assi_stat => "赋值语句",
+        Rule::fn_expr => "匿名函数",
+        Rule::function => "fn",
+        Rule::argu_list | Rule::type_list => "参数列表",
+        Rule::call_args => "调用参数",
+        Rule::index => "下标",
+        Rule::array_l => "数组",
+        Rule::map_l => "map",
+        Rule::field => ".",
+        Rule::struct_l => "结构体",
+        Rule::struct_field => "字段",
+        Rule::enum_l => "枚举",
+        Rule::variant => "枚举成员",
+        Rule::match_arm => "match 分支",
+        Rule::bindings => "绑定",
+        Rule::array_suffix => "[]",
+        Rule::add => "+",
+        Rule::subtract | Rule::unary_minus => "-",
+        Rule::multiply => "*",
+        Rule::divide => "/",
+        Rule::modulo => "%",
+        Rule::assign => "=",
+        Rule::not => "!",
+        Rule::eq => "==",
+        Rule::not_eq => "!=",
+        Rule::large => ">",
+        Rule::less => "<",
+        Rule::large_eq => ">=",
+        Rule::less_eq => "<=",
+        Rule::and => "&&",
+        Rule::or => "||",
+        rule => return format!("{:?}", rule),
+    };
+    name.to_string()
 }
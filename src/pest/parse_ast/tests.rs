@@ -9,21 +9,47 @@ mod tests {
     #[test]
     fn test_fn_call() {
         let binding = String::from("a(b,c(f),99)");
-        let pairs = CalculatorParser::parse(Rule::fn_call, &binding).unwrap();
+        let pairs = CalculatorParser::parse(Rule::additive, &binding).unwrap();
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_fn_call(p);
+        let res = parse_add(p).unwrap();
         println!("{:#?}", res);
 
+        let AstNodeType::FnCall { argu_list, .. } = res else {
+            panic!("应该是函数调用")
+        };
+        assert_eq!(argu_list.len(), 3);
+    }
+
+    #[test]
+    // 后缀调用可以连续, 比一元运算优先
+    fn test_postfix_call() {
+        let binding = String::from("-make_adder(1)(2)");
+        let pairs = CalculatorParser::parse(Rule::additive, &binding).unwrap();
+        let res = parse_add(pairs.peek().unwrap()).unwrap();
         match res {
-            AstNodeType::FnCall {
-                identifier,
-                argu_list,
-            } => {
-                assert_eq!(argu_list.len(), 3);
-            }
-            _ => {}
+            AstNodeType::UnaryExp { exp, .. } => match *exp {
+                AstNodeType::FnCall {
+                    identifier,
+                    argu_list,
+                    span,
+                } => {
+                    assert_eq!(argu_list.len(), 1);
+                    assert_eq!(span, Span::new(1, 17));
+                    match *identifier {
+                        AstNodeType::FnCall { identifier, .. } => match *identifier {
+                            AstNodeType::Identifier { ident, .. } => {
+                                assert_eq!(ident, "make_adder")
+                            }
+                            _ => panic!("最里面应该是标识符"),
+                        },
+                        _ => panic!("callee应该是FnCall"),
+                    }
+                }
+                _ => panic!("应该是函数调用"),
+            },
+            _ => panic!("应该是一元运算"),
         }
     }
 
@@ -39,7 +65,7 @@ mod tests {
         // println!("{:#?}", res);
         match res.unwrap() {
             DeclareType::FnType { return_type, argu_list } => {
-                assert_eq!(return_type.is_none(), false);
+                assert!(return_type.is_some());
                 assert_eq!(argu_list.len(), 2);
             },
             _ => {
@@ -49,6 +75,90 @@ mod tests {
 
     }
 
+    #[test]
+    // bool和string可以用在声明, fn类型和参数列表里
+    fn test_bool_string_type() {
+        let code = String::from(
+            "bool ok = 1 < 2;\nstring s;\nfn bool (string, bool) f = g;\nbool h (string a, bool b) { return b; }",
+        );
+        let asts = parse_code(code).unwrap();
+        assert_eq!(asts.len(), 4);
+        let child = |index: usize| match &asts[index] {
+            AstNodeType::Statement { child, .. } => child[0].clone(),
+            _ => panic!("应该是语句"),
+        };
+        match child(0) {
+            AstNodeType::Declaration { declare_type, .. } => {
+                assert_eq!(declare_type, DeclareType::Bool)
+            }
+            _ => panic!("应该是声明"),
+        }
+        match child(1) {
+            AstNodeType::Declaration { declare_type, .. } => {
+                assert_eq!(declare_type, DeclareType::String)
+            }
+            _ => panic!("应该是声明"),
+        }
+        match child(2) {
+            AstNodeType::Declaration { declare_type, .. } => {
+                assert_eq!(declare_type.to_string(), "fn bool (string, bool)")
+            }
+            _ => panic!("应该是声明"),
+        }
+        match child(3) {
+            AstNodeType::FnDeclaration {
+                return_type,
+                argu_list,
+                ..
+            } => {
+                assert_eq!(return_type, Some(Box::new(DeclareType::Bool)));
+                assert_eq!(argu_list.len(), 2);
+            }
+            _ => panic!("应该是函数声明"),
+        }
+    }
+
+    #[test]
+    fn test_array() {
+        let asts = parse_code(String::from("int[][] a = [[1], []];\na[0][1] = a[1][0];")).unwrap();
+        match &asts[0] {
+            AstNodeType::Statement { child, .. } => match &child[0] {
+                AstNodeType::Declaration { declare_type, .. } => {
+                    assert_eq!(declare_type.to_string(), "int[][]")
+                }
+                _ => panic!("应该是声明"),
+            },
+            _ => panic!("应该是语句"),
+        }
+        match &asts[1] {
+            AstNodeType::Statement { child, .. } => match &child[0] {
+                AstNodeType::IndexAssignment {
+                    target,
+                    index,
+                    additive,
+                    ..
+                } => {
+                    assert!(matches!(**target, AstNodeType::IndexExp { .. }));
+                    assert!(matches!(**index, AstNodeType::IntLiteral(1, _)));
+                    assert!(matches!(**additive, AstNodeType::IndexExp { .. }));
+                }
+                _ => panic!("应该是下标赋值"),
+            },
+            _ => panic!("应该是语句"),
+        }
+    }
+
+    #[test]
+    // 解析不了的类型要报错
+    fn test_unsupported_type() {
+        let binding = String::from("void");
+        let pairs = CalculatorParser::parse(Rule::type_or_void, &binding).unwrap();
+        match parse_type(pairs.peek().unwrap()) {
+            Err(SimpleError::Ast(message)) => assert_eq!(message, "不支持的类型 void"),
+            _ => panic!("应该报错"),
+        }
+    }
+
     #[test]
     //方法申明
     fn test_fn_declere() {
@@ -57,16 +167,20 @@ mod tests {
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_fn_decare(p);
+        let res = parse_fn_decare(p).unwrap();
         // println!("{:#?}", res);
-        match res {
-          AstNodeType::FnDeclaration { return_type, identifier, argu_list, block } => {
-              assert_eq!(return_type.is_none(), true);
-              assert_eq!(identifier, String::from("a"));
-              assert_eq!(argu_list.len(), 2);
-          },
-          _ => {}
-        }
+        let AstNodeType::FnDeclaration {
+            return_type,
+            identifier,
+            argu_list,
+            ..
+        } = res
+        else {
+            panic!("应该是函数声明")
+        };
+        assert!(return_type.is_none());
+        assert_eq!(identifier, String::from("a"));
+        assert_eq!(argu_list.len(), 2);
     }
 
     #[test]
@@ -80,12 +194,10 @@ mod tests {
         let res = parse(p).unwrap();
         println!("{:#?}", res);
 
-        match &res {
-            AstNodeType::Declaration { declare_type, identifier, additive } => {
-                assert_eq!(identifier.as_str(), String::from("a"));
-            }
-            _ => {}
-        }
+        let AstNodeType::Declaration { identifier, .. } = &res else {
+            panic!("应该是变量声明")
+        };
+        assert_eq!(identifier.as_str(), String::from("a"));
     }
 
     #[test]
@@ -95,19 +207,13 @@ mod tests {
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_stat(p.into_inner());
+        let res = parse_stat(p.into_inner()).unwrap();
         // println!("{:#?}", res);
         assert_eq!(res.len(), 1);
-        match &res[0] {
-            AstNodeType::Declaration {
-                declare_type,
-                identifier,
-                additive,
-            } => {
-                assert_eq!(identifier, "b")
-            }
-            _ => {}
-        }
+        let AstNodeType::Declaration { identifier, .. } = &res[0] else {
+            panic!("应该是变量声明")
+        };
+        assert_eq!(identifier, "b")
     }
 
     #[test]
@@ -116,11 +222,11 @@ mod tests {
         let pairs = CalculatorParser::parse(Rule::equation, &binding).unwrap();
         let p = pairs;
         // println!("{:#?}", p);
-        let rss = parse_simple(p);
-        let res = rss.get(0).unwrap();
+        let rss = parse_simple(p).unwrap();
+        let res = rss.first().unwrap();
         // println!("{:#?}", res);
         match &res {
-            AstNodeType::Statement { child } => {
+            AstNodeType::Statement { child, .. } => {
                 assert_eq!(child.len(), 1)
             }
             _ => {
@@ -129,6 +235,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_return_void() {
+        let binding = String::from("return;");
+        let pairs = CalculatorParser::parse(Rule::statement, &binding).unwrap();
+        let p = pairs.peek().unwrap();
+
+        let res = parse_stat(p.into_inner()).unwrap();
+        match &res[0] {
+            AstNodeType::ReturnExp { exp, .. } => {
+                assert!(exp.is_none())
+            }
+            _ => {
+                panic!("return 语句出错")
+            }
+        }
+    }
+
     #[test]
     fn test_if() {
         let binding = String::from("if (8>4) {a;} else {b;}");
@@ -136,18 +259,85 @@ mod tests {
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_if(p);
+        let res = parse_if(p).unwrap();
         println!("{:#?}", res);
 
+        let AstNodeType::IfStatement { else_stat, .. } = &res else {
+            panic!("应该是if语句")
+        };
+        assert!(else_stat.is_some())
+    }
+
+    #[test]
+    fn test_while() {
+        let binding = String::from("while (i < 3) {i = i + 1; break;}");
+        let pairs = CalculatorParser::parse(Rule::while_statement, &binding).unwrap();
+        let p = pairs.peek().unwrap();
+
+        let res = parse_while(p).unwrap();
+        match &res {
+            AstNodeType::WhileStatement {
+                judge_stat, block, ..
+            } => {
+                assert!(matches!(**judge_stat, AstNodeType::JudgeExp { .. }));
+                assert!(
+                    matches!(**block, AstNodeType::Block { statements: Some(ref s), .. } if s.len() == 2)
+                );
+            }
+            _ => {
+                panic!("while 解析出错")
+            }
+        }
+    }
+
+    #[test]
+    fn test_for() {
+        let binding = String::from("for (int i = 0; i < 3; i = i + 1) {continue;}");
+        let pairs = CalculatorParser::parse(Rule::for_statement, &binding).unwrap();
+        let p = pairs.peek().unwrap();
+
+        let res = parse_for(p).unwrap();
         match &res {
-            AstNodeType::IfStatement {
+            AstNodeType::ForStatement {
+                init,
                 judge_stat,
-                if_stat,
-                else_stat,
+                step,
+                ..
             } => {
-                assert_eq!(else_stat.is_none(), false)
+                assert!(matches!(
+                    init.as_deref(),
+                    Some(AstNodeType::Declaration { .. })
+                ));
+                assert!(matches!(
+                    judge_stat.as_deref(),
+                    Some(AstNodeType::JudgeExp { .. })
+                ));
+                assert!(matches!(
+                    step.as_deref(),
+                    Some(AstNodeType::AssignmentStatement { .. })
+                ));
+            }
+            _ => {
+                panic!("for 解析出错")
+            }
+        }
+
+        // 三部分都可以省略
+        let binding = String::from("for (;;) {break;}");
+        let pairs = CalculatorParser::parse(Rule::for_statement, &binding).unwrap();
+        let res = parse_for(pairs.peek().unwrap()).unwrap();
+        match &res {
+            AstNodeType::ForStatement {
+                init,
+                judge_stat,
+                step,
+                ..
+            } => {
+                assert!(init.is_none() && judge_stat.is_none() && step.is_none());
+            }
+            _ => {
+                panic!("for 解析出错")
             }
-            _ => {}
         }
     }
 
@@ -158,37 +348,118 @@ mod tests {
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_block(p);
+        let res = parse_block(p).unwrap();
         // println!("{:#?}", res);
 
-        match &res {
-            AstNodeType::Block { statements } => match statements {
-                Some(s) => {
-                    assert_eq!(s.len(), 1)
-                }
-                None => {
-                    assert_eq!(1, 0)
-                }
-            },
-            _ => {}
+        let AstNodeType::Block { statements, .. } = &res else {
+            panic!("应该是block")
+        };
+        match statements {
+            Some(s) => {
+                assert_eq!(s.len(), 1)
+            }
+            None => {
+                panic!("block里应该有语句")
+            }
         }
     }
 
     #[test]
     fn test_judge() {
         let binding = String::from("8>5");
-        let pairs = CalculatorParser::parse(Rule::judge_stat, &binding).unwrap();
+        let pairs = CalculatorParser::parse(Rule::additive, &binding).unwrap();
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_judge(p);
+        let res = parse_add(p).unwrap();
         // println!("{:#?}", res);
 
-        match &res {
-            AstNodeType::JudgeExp { left, right, judge } => {
-                assert_eq!(judge, ">")
+        let AstNodeType::JudgeExp { judge, .. } = &res else {
+            panic!("应该是比较表达式")
+        };
+        assert_eq!(judge, ">")
+    }
+
+    #[test]
+    // 优先级: 算术 > 比较 > && > ||
+    fn test_logic() {
+        let binding = String::from("a || !b && 1 + 1 >= 2");
+        let pairs = CalculatorParser::parse(Rule::additive, &binding).unwrap();
+        let p = pairs.peek().unwrap();
+
+        let res = parse_add(p).unwrap();
+        match res {
+            AstNodeType::LogicExp {
+                op, left, right, ..
+            } => {
+                assert_eq!(op, "||");
+                assert!(matches!(*left, AstNodeType::Identifier { .. }));
+                match *right {
+                    AstNodeType::LogicExp {
+                        op, left, right, ..
+                    } => {
+                        assert_eq!(op, "&&");
+                        assert!(matches!(*left, AstNodeType::UnaryExp { ref op, .. } if op == "!"));
+                        assert!(
+                            matches!(*right, AstNodeType::JudgeExp { ref judge, .. } if judge == ">=")
+                        );
+                    }
+                    _ => panic!("&& 解析出错"),
+                }
+            }
+            _ => {
+                panic!("|| 解析出错")
+            }
+        }
+    }
+
+    #[test]
+    // true/false是字面量, 不是标识符
+    fn test_bool_literal() {
+        let binding = String::from("true != falsey");
+        let pairs = CalculatorParser::parse(Rule::additive, &binding).unwrap();
+        let p = pairs.peek().unwrap();
+
+        let res = parse_add(p).unwrap();
+        match res {
+            AstNodeType::JudgeExp {
+                left, right, judge, ..
+            } => {
+                assert_eq!(judge, "!=");
+                assert!(matches!(*left, AstNodeType::BoolLiteral(true, _)));
+                assert!(
+                    matches!(*right, AstNodeType::Identifier { ref ident, .. } if ident == "falsey")
+                );
+            }
+            _ => {
+                panic!("bool 解析出错")
+            }
+        }
+    }
+
+    #[test]
+    // 字符串里的空格和转义要保留
+    fn test_string_literal() {
+        let binding = String::from(r#"string s = " a\"b\n""#);
+        let pairs = CalculatorParser::parse(Rule::declare_stat, &binding).unwrap();
+        let p = pairs.peek().unwrap();
+
+        let res = parse(p).unwrap();
+        match res {
+            AstNodeType::Declaration {
+                declare_type,
+                additive,
+                ..
+            } => {
+                assert!(matches!(declare_type, DeclareType::String));
+                match *additive.unwrap() {
+                    AstNodeType::StringLiteral(s, _) => assert_eq!(s, " a\"b\n"),
+                    _ => panic!("字符串解析出错"),
+                }
+            }
+            _ => {
+                panic!("字符串声明解析出错")
             }
-            _ => {}
         }
     }
 
@@ -199,7 +470,7 @@ mod tests {
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_stat(p.into_inner());
+        let res = parse_stat(p.into_inner()).unwrap();
         // println!("{:#?}", res);
         assert_eq!(res.len(), 1);
     }
@@ -211,30 +482,28 @@ mod tests {
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_stat(p.into_inner());
+        let res = parse_stat(p.into_inner()).unwrap();
         // println!("{:#?}", res);
         assert_eq!(res.len(), 1);
-        match &res[0] {
-            AstNodeType::AssignmentStatement { ident, additive } => {
-                assert_eq!(ident, "b")
-            }
-            _ => {}
-        }
+        let AstNodeType::AssignmentStatement { ident, .. } = &res[0] else {
+            panic!("应该是赋值语句")
+        };
+        assert_eq!(ident, "b")
     }
 
     #[test]
     fn test_multi() {
         let binding = String::from("a*2");
-        let pairs = CalculatorParser::parse(Rule::multi, &binding).unwrap();
+        let pairs = CalculatorParser::parse(Rule::additive, &binding).unwrap();
         let p = pairs.peek().unwrap();
-        let res = parse_mul(p);
+        let res = parse_add(p).unwrap();
         // println!("{:#?}", res);
         match res {
-            AstNodeType::MulitiExp { child } => {
-                assert_eq!(child.len(), 2)
+            AstNodeType::BinaryExp { op, .. } => {
+                assert_eq!(op, "*")
             }
             _ => {
-                //
+                panic!("乘法解析出错")
             }
         }
     }
@@ -246,20 +515,300 @@ mod tests {
         let p = pairs.peek().unwrap();
         // println!("{:#?}", p);
 
-        let res = parse_add(p);
+        let res = parse_add(p).unwrap();
         // println!("{:#?}", res);
         match res {
-            AstNodeType::AdditiveExp { child } => {
-                assert_eq!(child.len(), 2)
+            AstNodeType::BinaryExp {
+                op, left, right, ..
+            } => {
+                assert_eq!(op, "+");
+                assert!(matches!(*left, AstNodeType::IntLiteral(3, _)));
+                assert!(matches!(*right, AstNodeType::BinaryExp { ref op, .. } if op == "*"));
+            }
+            _ => {
+                panic!("加法解析出错")
+            }
+        }
+    }
+
+    #[test]
+    // 左结合: 8-2-1 => (8-2)-1
+    fn test_sub_assoc() {
+        let binding = String::from("8-2-1");
+        let pairs = CalculatorParser::parse(Rule::additive, &binding).unwrap();
+        let p = pairs.peek().unwrap();
+
+        let res = parse_add(p).unwrap();
+        match res {
+            AstNodeType::BinaryExp {
+                op, left, right, ..
+            } => {
+                assert_eq!(op, "-");
+                assert!(matches!(*left, AstNodeType::BinaryExp { ref op, .. } if op == "-"));
+                assert!(matches!(*right, AstNodeType::IntLiteral(1, _)));
+            }
+            _ => {
+                panic!("减法解析出错")
+            }
+        }
+    }
+
+    #[test]
+    // 一元负号和括号: -(1+2)%3
+    fn test_unary_paren() {
+        let binding = String::from("-(1+2)%3");
+        let pairs = CalculatorParser::parse(Rule::additive, &binding).unwrap();
+        let p = pairs.peek().unwrap();
+
+        let res = parse_add(p).unwrap();
+        match res {
+            AstNodeType::BinaryExp { op, left, .. } => {
+                assert_eq!(op, "%");
+                match *left {
+                    AstNodeType::UnaryExp { op, exp, .. } => {
+                        assert_eq!(op, "-");
+                        assert!(matches!(*exp, AstNodeType::BinaryExp { ref op, .. } if op == "+"));
+                    }
+                    _ => panic!("一元负号解析出错"),
+                }
             }
             _ => {
-                //
+                panic!("取模解析出错")
             }
         }
     }
+
     #[test]
     fn test_main() {
         let res = parse_file();
-        println!("{:#?}", res)
+        println!("{:#?}", res);
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_span() {
+        let code = String::from("int a = 1;\nif (a > 0) {\n  a = b * 2;\n}");
+        let res = parse_code(code.clone()).unwrap();
+        assert_eq!(res[0].span(), Span::new(0, 10));
+        assert_eq!(res[1].span().line_col(&code), (2, 1));
+
+        // 找到 b * 2
+        let if_stat = match &res[1] {
+            AstNodeType::Statement { child, .. } => child[0].clone(),
+            _ => panic!("不是statement"),
+        };
+        let assign = match if_stat {
+            AstNodeType::IfStatement { if_stat, .. } => match *if_stat {
+                AstNodeType::Block {
+                    statements: Some(statements),
+                    ..
+                } => match &statements[0] {
+                    AstNodeType::Statement { child, .. } => child[0].clone(),
+                    _ => panic!("不是statement"),
+                },
+                _ => panic!("不是block"),
+            },
+            _ => panic!("不是if"),
+        };
+        match assign {
+            AstNodeType::AssignmentStatement { additive, span, .. } => {
+                assert_eq!(&code[span.start..span.end], "a = b * 2");
+                let mul_span = additive.span();
+                assert_eq!(&code[mul_span.start..mul_span.end], "b * 2");
+                assert_eq!(mul_span.line_col(&code), (3, 7));
+            }
+            _ => panic!("不是赋值"),
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let res = parse_code(String::from("int a = 3;\nb = 4 +* 2;"));
+        match res {
+            Err(SimpleError::Parse(diagnostic)) => {
+                assert_eq!(diagnostic.line, 2);
+                assert_eq!(diagnostic.column, 8);
+                assert_eq!(diagnostic.source_line, "b = 4 +* 2;");
+                assert!(diagnostic.expected.contains(&String::from("标识符")));
+                assert!(diagnostic.expected.contains(&String::from("整数")));
+                assert_eq!(
+                    diagnostic.to_string(),
+                    "语法错误 2:8, 期望: 整数, long, 浮点数, 字符串, true/false, 标识符, fn, -, !, 数组, map\n  |\n2 | b = 4 +* 2;\n  |        ^"
+                );
+            }
+            _ => {
+                panic!("应该是语法错误")
+            }
+        }
+    }
+
+    #[test]
+    fn test_number_literal() {
+        let literal = |code: &str| {
+            let asts = parse_code(format!("{};", code)).unwrap();
+            match &asts[0] {
+                AstNodeType::Statement { child, .. } => match &child[0] {
+                    AstNodeType::IntLiteral(n, _) => format!("int {}", n),
+                    AstNodeType::LongLiteral(n, _) => format!("long {}", n),
+                    AstNodeType::FloatLiteral(f, _) => format!("float {}", f),
+                    _ => panic!("应该是数字"),
+                },
+                _ => panic!("应该是语句"),
+            }
+        };
+        assert_eq!(literal("1463895090"), "int 1463895090");
+        assert_eq!(literal("1463895090123L"), "long 1463895090123");
+        assert_eq!(literal("3.1415927"), "float 3.1415927");
+        assert_eq!(literal("1e9"), "float 1000000000");
+        assert_eq!(literal("2.5E-3"), "float 0.0025");
+        // 超出int范围的字面量不会被截断, 报错带上字面量的位置
+        match parse_code(String::from("int a = 1;\nint b = 3000000000;")) {
+            Err(SimpleError::Parse(diagnostic)) => {
+                assert_eq!((diagnostic.line, diagnostic.column), (2, 9));
+                assert_eq!(
+                    diagnostic.message.as_deref(),
+                    Some("3000000000 超出 int 的范围, long 要写成 3000000000L")
+                );
+                assert_eq!(diagnostic.source_line, "int b = 3000000000;");
+            }
+            _ => panic!("应该报错"),
+        }
+        match parse_code(String::from("99999999999999999999L;")) {
+            Err(SimpleError::Parse(diagnostic)) => {
+                assert_eq!((diagnostic.line, diagnostic.column), (1, 1));
+                assert_eq!(
+                    diagnostic.message.as_deref(),
+                    Some("99999999999999999999L 超出 long 的范围")
+                );
+            }
+            _ => panic!("应该报错"),
+        }
+        // 太大的浮点数不会变成inf
+        match parse_code(String::from("float f = 1e400;")) {
+            Err(SimpleError::Parse(diagnostic)) => {
+                assert_eq!((diagnostic.line, diagnostic.column), (1, 11));
+                assert_eq!(
+                    diagnostic.message.as_deref(),
+                    Some("1e400 超出 float 的范围")
+                );
+            }
+            _ => panic!("应该报错"),
+        }
+    }
+
+    #[test]
+    fn test_parse_error_missing_semicolon() {
+        let res = parse_code(String::from("int a = ;"));
+        match res {
+            Err(SimpleError::Parse(diagnostic)) => {
+                assert_eq!((diagnostic.line, diagnostic.column), (1, 9));
+                assert_eq!(diagnostic.expected, vec![String::from("表达式")]);
+            }
+            _ => {
+                panic!("应该是语法错误")
+            }
+        }
+    }
+
+    #[test]
+    fn test_fn_expression() {
+        let asts = parse_code(String::from("apply(fn int (int x) { return x; }, 1);")).unwrap();
+        match &asts[0] {
+            AstNodeType::Statement { child, .. } => match &child[0] {
+                AstNodeType::FnCall { argu_list, .. } => match &argu_list[0] {
+                    AstNodeType::FnExpression {
+                        return_type,
+                        argu_list,
+                        ..
+                    } => {
+                        assert_eq!(return_type, &Some(Box::new(DeclareType::Int)));
+                        assert_eq!(argu_list.len(), 1);
+                    }
+                    _ => panic!("参数应该是匿名函数"),
+                },
+                _ => panic!("应该是函数调用"),
+            },
+            _ => panic!("应该是语句"),
+        }
+    }
+
+    #[test]
+    // a[0].x = 1, 先下标再取字段
+    fn test_field_assignment() {
+        let asts = parse_code(String::from("a[0].x = 1;")).unwrap();
+        match &asts[0] {
+            AstNodeType::Statement { child, .. } => match &child[0] {
+                AstNodeType::FieldAssignment { target, field, .. } => {
+                    assert_eq!(field, "x");
+                    assert!(matches!(target.as_ref(), AstNodeType::IndexExp { .. }));
+                }
+                _ => panic!("应该是字段赋值"),
+            },
+            _ => panic!("应该是语句"),
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let code = "match (Shape::Rect(1, 2)) { Rect(w, h) => { w; } Empty => { } _ => { } }";
+        let asts = parse_code(String::from(code)).unwrap();
+        match &asts[0] {
+            AstNodeType::Statement { child, .. } => match &child[0] {
+                AstNodeType::MatchStatement { target, arms, .. } => {
+                    match target.as_ref() {
+                        AstNodeType::EnumLiteral { variant, args, .. } => {
+                            assert_eq!(variant, "Rect");
+                            assert_eq!(args.len(), 2);
+                        }
+                        _ => panic!("应该是枚举"),
+                    }
+                    let patterns: Vec<_> = arms
+                        .iter()
+                        .map(|arm| (arm.variant.clone(), arm.bindings.clone()))
+                        .collect();
+                    assert_eq!(
+                        patterns,
+                        vec![
+                            (
+                                Some(String::from("Rect")),
+                                vec![String::from("w"), String::from("h")]
+                            ),
+                            (Some(String::from("Empty")), vec![]),
+                            (None, vec![]),
+                        ]
+                    );
+                }
+                _ => panic!("应该是 match"),
+            },
+            _ => panic!("应该是语句"),
+        }
+    }
+
+    #[test]
+    // 注释、tab和CRLF都当作空白
+    fn test_comment_and_whitespace() {
+        let code = "// 行注释\r\nint a = 1; /* 块注释\r\n 可以跨行 */\r\n\tif (a > 0) {\r\n\t\ta = a / 1; // 不是除号\r\n\t}\r\n";
+        let asts = parse_code(String::from(code)).unwrap();
+        assert_eq!(asts.len(), 2);
+        match &asts[1] {
+            AstNodeType::Statement { child, .. } => match &child[0] {
+                AstNodeType::IfStatement { if_stat, .. } => match if_stat.as_ref() {
+                    AstNodeType::Block { statements, .. } => {
+                        assert_eq!(statements.as_ref().unwrap().len(), 1)
+                    }
+                    _ => panic!("if 分支应该是 block"),
+                },
+                _ => panic!("应该是 if 语句"),
+            },
+            _ => panic!("应该是语句"),
+        }
+        // 错误位置按行号算, 不受\r影响
+        match parse_code(String::from("int a = 1;\r\n\tint b = ;\r\n")) {
+            Err(SimpleError::Parse(diagnostic)) => {
+                assert_eq!((diagnostic.line, diagnostic.column), (2, 10));
+                assert_eq!(diagnostic.source_line, "\tint b = ;");
+            }
+            _ => panic!("应该是语法错误"),
+        }
     }
 }
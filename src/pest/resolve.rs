@@ -0,0 +1,327 @@
+use std::{collections::HashMap, rc::Rc};
+
+use super::parse_ast::{Address, AstNodeType};
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod tests;
+
+/**
+ * 局部作用域里的一个变量, index是槽位
+ * hoisted是提前登记的函数, 执行到声明之前只在函数体里可见
+ */
+struct Slot {
+    index: usize,
+    hoisted: bool,
+}
+
+/**
+ * 静态解析, 给每个变量填上运行时的位置
+ * 作用域要和执行时一一对应: Block, for, match分支, 函数参数各是一层
+ * 全局作用域不在scopes里, 局部找不到的变量都在globals里按名字分配槽位
+ */
+struct Resolver<'a> {
+    scopes: Vec<HashMap<String, Slot>>,
+    // 进入函数体时作用域的层数
+    fn_depths: Vec<usize>,
+    // 全局变量名字到槽位的表, 顶层语句之间共用
+    globals: &'a mut HashMap<String, usize>,
+}
+
+/**
+ * 解析一条顶层语句, 执行前调用
+ * globals是之前的顶层语句分配好的全局槽位, 新出现的名字接着往后分配
+ */
+pub fn resolve(ast: &mut AstNodeType, globals: &mut HashMap<String, usize>) {
+    let mut resolver = Resolver {
+        scopes: vec![],
+        fn_depths: vec![],
+        globals,
+    };
+    resolver.resolve(ast);
+}
+
+impl Resolver<'_> {
+    // 在当前作用域声明变量, 同一个作用域里重复声明用同一个槽位
+    fn declare(&mut self, ident: &str) -> Address {
+        let Some(scope) = self.scopes.last_mut() else {
+            return self.global(ident);
+        };
+        let next = scope.len();
+        let slot = scope.entry(ident.to_string()).or_insert(Slot {
+            index: next,
+            hoisted: false,
+        });
+        slot.hoisted = false;
+        Address::Local {
+            depth: 0,
+            slot: slot.index,
+        }
+    }
+
+    // 全局变量按名字分配槽位, 声明之前用到的名字也先占一个, 声明时用同一个
+    fn global(&mut self, ident: &str) -> Address {
+        let next = self.globals.len();
+        let slot = *self.globals.entry(ident.to_string()).or_insert(next);
+        Address::Global { slot }
+    }
+
+    // 从里往外找变量, 和TypeChecker::lookup的规则一样
+    fn lookup(&mut self, ident: &str) -> Address {
+        let fn_depth = self.fn_depths.last().copied().unwrap_or(0);
+        let local = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, scope)| {
+                scope
+                    .get(ident)
+                    .filter(|slot| !slot.hoisted || index < fn_depth)
+                    .map(|slot| Address::Local {
+                        depth: self.scopes.len() - 1 - index,
+                        slot: slot.index,
+                    })
+            });
+        local.unwrap_or_else(|| self.global(ident))
+    }
+
+    /**
+     * 提前给块里声明的函数分配槽位, 函数体里可以调用后面才声明的函数
+     * 执行时这些槽位在块里的语句之前就绑定好了
+     */
+    fn hoist(&mut self, stats: &[AstNodeType]) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        for stat in stats {
+            let AstNodeType::Statement { child, .. } = stat else {
+                continue;
+            };
+            for exp in child.iter() {
+                if let AstNodeType::FnDeclaration { identifier, .. } = exp {
+                    let next = scope.len();
+                    scope.entry(identifier.clone()).or_insert(Slot {
+                        index: next,
+                        hoisted: true,
+                    });
+                }
+            }
+        }
+    }
+
+    // 参数一层作用域, 和调用时bind_params用的作用域对应, 函数体的Block在里面
+    // 执行前还没有闭包引用函数体, make_mut不会复制
+    fn resolve_fn(&mut self, argu_list: &mut Rc<Vec<AstNodeType>>, block: &mut Rc<AstNodeType>) {
+        self.scopes.push(HashMap::new());
+        self.resolve_all(Rc::make_mut(argu_list).as_mut_slice());
+        self.fn_depths.push(self.scopes.len());
+        self.resolve(Rc::make_mut(block));
+        self.fn_depths.pop();
+        self.scopes.pop();
+    }
+
+    fn resolve_all(&mut self, asts: &mut [AstNodeType]) {
+        for ast in asts {
+            self.resolve(ast);
+        }
+    }
+
+    fn resolve_option(&mut self, ast: &mut Option<Box<AstNodeType>>) {
+        if let Some(ast) = ast {
+            self.resolve(ast);
+        }
+    }
+
+    fn resolve(&mut self, ast: &mut AstNodeType) {
+        match ast {
+            AstNodeType::IntLiteral(..)
+            | AstNodeType::LongLiteral(..)
+            | AstNodeType::FloatLiteral(..)
+            | AstNodeType::BoolLiteral(..)
+            | AstNodeType::StringLiteral(..)
+            | AstNodeType::StructDeclaration { .. }
+            | AstNodeType::EnumDeclaration { .. }
+            | AstNodeType::BreakStatement { .. }
+            | AstNodeType::ContinueStatement { .. } => {}
+
+            AstNodeType::Statement { child, .. } => self.resolve_all(child),
+
+            AstNodeType::BinaryExp { left, right, .. }
+            | AstNodeType::JudgeExp { left, right, .. }
+            | AstNodeType::LogicExp { left, right, .. } => {
+                self.resolve(left);
+                self.resolve(right);
+            }
+
+            AstNodeType::UnaryExp { exp, .. } => self.resolve(exp),
+
+            AstNodeType::IfStatement {
+                judge_stat,
+                if_stat,
+                else_stat,
+                ..
+            } => {
+                self.resolve(judge_stat);
+                self.resolve(if_stat);
+                self.resolve_option(else_stat);
+            }
+
+            AstNodeType::Block { statements, .. } => {
+                self.scopes.push(HashMap::new());
+                if let Some(stats) = statements {
+                    self.hoist(stats);
+                    self.resolve_all(stats);
+                }
+                self.scopes.pop();
+            }
+
+            AstNodeType::Identifier { ident, address, .. } => *address = self.lookup(ident),
+
+            AstNodeType::AssignmentStatement {
+                ident,
+                address,
+                additive,
+                ..
+            } => {
+                self.resolve(additive);
+                *address = self.lookup(ident);
+            }
+
+            // 先声明再解析函数体, 函数体里可以递归调用自己
+            AstNodeType::FnDeclaration {
+                identifier,
+                address,
+                argu_list,
+                block,
+                ..
+            } => {
+                *address = self.declare(identifier);
+                self.resolve_fn(argu_list, block);
+            }
+
+            AstNodeType::FnExpression {
+                argu_list, block, ..
+            } => self.resolve_fn(argu_list, block),
+
+            AstNodeType::FnCall {
+                identifier,
+                argu_list,
+                ..
+            } => {
+                self.resolve(identifier);
+                self.resolve_all(argu_list);
+            }
+
+            // 初始值和参数的默认值在声明之前计算, 用的是外面的同名变量
+            AstNodeType::Declaration {
+                identifier,
+                address,
+                additive,
+                ..
+            } => {
+                self.resolve_option(additive);
+                *address = self.declare(identifier);
+            }
+
+            AstNodeType::VarDeclaration {
+                identifier,
+                address,
+                additive,
+                ..
+            } => {
+                self.resolve(additive);
+                *address = self.declare(identifier);
+            }
+
+            AstNodeType::ArrayLiteral { elements, .. } => self.resolve_all(elements),
+
+            AstNodeType::MapLiteral { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve(key);
+                    self.resolve(value);
+                }
+            }
+
+            AstNodeType::IndexExp { target, index, .. } => {
+                self.resolve(target);
+                self.resolve(index);
+            }
+
+            AstNodeType::IndexAssignment {
+                target,
+                index,
+                additive,
+                ..
+            } => {
+                self.resolve(target);
+                self.resolve(index);
+                self.resolve(additive);
+            }
+
+            AstNodeType::StructLiteral { fields, .. } => {
+                for (_, value, _) in fields {
+                    self.resolve(value);
+                }
+            }
+
+            AstNodeType::FieldExp { target, .. } => self.resolve(target),
+
+            AstNodeType::FieldAssignment {
+                target, additive, ..
+            } => {
+                self.resolve(target);
+                self.resolve(additive);
+            }
+
+            AstNodeType::EnumLiteral { args, .. } => self.resolve_all(args),
+
+            // 每个分支一层作用域, 绑定的值按顺序占槽位, 同名的后面覆盖前面
+            AstNodeType::MatchStatement { target, arms, .. } => {
+                self.resolve(target);
+                for arm in arms {
+                    let bindings = arm
+                        .bindings
+                        .iter()
+                        .enumerate()
+                        .map(|(index, binding)| {
+                            let slot = Slot {
+                                index,
+                                hoisted: false,
+                            };
+                            (binding.clone(), slot)
+                        })
+                        .collect();
+                    self.scopes.push(bindings);
+                    self.resolve(&mut arm.block);
+                    self.scopes.pop();
+                }
+            }
+
+            AstNodeType::WhileStatement {
+                judge_stat, block, ..
+            } => {
+                self.resolve(judge_stat);
+                self.resolve(block);
+            }
+
+            // for自己一层作用域, 存放init里声明的变量
+            AstNodeType::ForStatement {
+                init,
+                judge_stat,
+                step,
+                block,
+                ..
+            } => {
+                self.scopes.push(HashMap::new());
+                self.resolve_option(init);
+                self.resolve_option(judge_stat);
+                self.resolve_option(step);
+                self.resolve(block);
+                self.scopes.pop();
+            }
+
+            AstNodeType::ReturnExp { exp, .. } => self.resolve_option(exp),
+        }
+    }
+}
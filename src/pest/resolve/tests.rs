@@ -0,0 +1,225 @@
+use super::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pest::parse_ast::parse_code;
+
+    // 解析后按出现的顺序收集变量的名字和位置, 只覆盖测试用到的节点
+    fn collect(ast: &AstNodeType, out: &mut Vec<(String, Address)>) {
+        match ast {
+            AstNodeType::Statement { child, .. } => child.iter().for_each(|a| collect(a, out)),
+            AstNodeType::Block {
+                statements: Some(stats),
+                ..
+            } => stats.iter().for_each(|a| collect(a, out)),
+            AstNodeType::BinaryExp { left, right, .. } => {
+                collect(left, out);
+                collect(right, out);
+            }
+            AstNodeType::Identifier { ident, address, .. } => out.push((ident.clone(), *address)),
+            AstNodeType::Declaration {
+                identifier,
+                address,
+                additive,
+                ..
+            } => {
+                if let Some(additive) = additive {
+                    collect(additive, out);
+                }
+                out.push((identifier.clone(), *address));
+            }
+            AstNodeType::AssignmentStatement {
+                ident,
+                address,
+                additive,
+                ..
+            } => {
+                collect(additive, out);
+                out.push((ident.clone(), *address));
+            }
+            AstNodeType::FnDeclaration {
+                identifier,
+                address,
+                argu_list,
+                block,
+                ..
+            } => {
+                out.push((identifier.clone(), *address));
+                argu_list.iter().for_each(|a| collect(a, out));
+                collect(block, out);
+            }
+            AstNodeType::FnCall {
+                identifier,
+                argu_list,
+                ..
+            } => {
+                collect(identifier, out);
+                argu_list.iter().for_each(|a| collect(a, out));
+            }
+            AstNodeType::ReturnExp { exp: Some(exp), .. } => collect(exp, out),
+            AstNodeType::ForStatement {
+                init,
+                judge_stat,
+                step,
+                block,
+                ..
+            } => {
+                for ast in [init, judge_stat, step].into_iter().flatten() {
+                    collect(ast, out);
+                }
+                collect(block, out);
+            }
+            AstNodeType::JudgeExp { left, right, .. } => {
+                collect(left, out);
+                collect(right, out);
+            }
+            AstNodeType::MatchStatement { target, arms, .. } => {
+                collect(target, out);
+                arms.iter().for_each(|arm| collect(&arm.block, out));
+            }
+            _ => {}
+        }
+    }
+
+    fn resolve_code(code: &str) -> Vec<(String, Address)> {
+        let mut out = vec![];
+        let mut globals = HashMap::new();
+        for mut ast in parse_code(code.to_string()).unwrap() {
+            resolve(&mut ast, &mut globals);
+            collect(&ast, &mut out);
+        }
+        out
+    }
+
+    fn local(name: &str, depth: usize, slot: usize) -> (String, Address) {
+        (name.to_string(), Address::Local { depth, slot })
+    }
+
+    fn global(name: &str, slot: usize) -> (String, Address) {
+        (name.to_string(), Address::Global { slot })
+    }
+
+    #[test]
+    // 顶层是全局变量, 参数和函数体各是一层, 按声明顺序分配槽位
+    fn test_slots() {
+        let code = r#"int a = 1;
+        int f (int x, int y) {
+          int z = x + a;
+          {
+            z = z + y;
+          }
+          return z;
+        }"#;
+        assert_eq!(
+            resolve_code(code),
+            vec![
+                global("a", 0),
+                global("f", 1),
+                local("x", 0, 0),
+                local("y", 0, 1),
+                local("x", 1, 0),
+                global("a", 0),
+                local("z", 0, 0),
+                local("z", 1, 0),
+                local("y", 2, 1),
+                local("z", 1, 0),
+                local("z", 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    // 初始值里的同名变量是外层的, 同一个作用域重复声明用同一个槽位
+    fn test_shadowing() {
+        let code = r#"{
+          int a = 1;
+          int b = 2;
+          {
+            int a = a + b;
+          }
+          int b = a;
+        }"#;
+        assert_eq!(
+            resolve_code(code),
+            vec![
+                local("a", 0, 0),
+                local("b", 0, 1),
+                local("a", 1, 0),
+                local("b", 1, 1),
+                local("a", 0, 0),
+                local("a", 0, 0),
+                local("b", 0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    // 块里的函数先分配槽位, 只在函数体里能用到后面的函数
+    fn test_hoisted_fn() {
+        let code = r#"{
+          int c = b;
+          int a () { return b(); }
+          int b () { return 1; }
+        }"#;
+        assert_eq!(
+            resolve_code(code),
+            vec![
+                global("b", 0),
+                local("c", 0, 2),
+                local("a", 0, 0),
+                local("b", 2, 1),
+                local("b", 0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    // 全局变量的槽位在顶层语句之间保留, 声明之前用到的名字和声明用同一个槽位
+    fn test_global_slots() {
+        let code = r#"int f () { return g(); }
+        int g () { return 1; }
+        int x = f();
+        x = g();"#;
+        assert_eq!(
+            resolve_code(code),
+            vec![
+                global("f", 0),
+                global("g", 1),
+                global("g", 1),
+                global("f", 0),
+                global("x", 2),
+                global("g", 1),
+                global("x", 2),
+            ]
+        );
+    }
+
+    #[test]
+    // for的init和match的绑定各占一层作用域
+    fn test_for_and_match() {
+        let code = r#"enum Shape { Rect(int, int) }
+        {
+          Shape s = Shape::Rect(1, 2);
+          for (int i = 0; i < 3; i = i + 1) {
+            match (s) {
+              Rect(w, h) => { i = w + h; }
+            }
+          }
+        }"#;
+        assert_eq!(
+            resolve_code(code),
+            vec![
+                local("s", 0, 0),
+                local("i", 0, 0),
+                local("i", 0, 0),
+                local("i", 0, 0),
+                local("i", 0, 0),
+                local("s", 2, 0),
+                local("w", 1, 0),
+                local("h", 1, 1),
+                local("i", 3, 0),
+            ]
+        );
+    }
+}
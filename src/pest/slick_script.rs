@@ -1,155 +1,605 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, rc::Rc};
 
-use crate::{pest::frame::ScopeStruct, util::SimpleError};
+use indexmap::IndexMap;
+
+use crate::{
+    pest::frame::{MapKey, ScopeStruct},
+    util::{SimpleError, TraceFrame},
+};
 
 use super::{
     frame::{Frame, Scope, ValType},
-    parse_ast::{parse_code, parse_file, AstNodeType},
+    parse_ast::{parse_code, read_file, Address, AstNodeType},
+    resolve::resolve,
+    type_check::type_check,
 };
 
-fn main() {
+// 默认的调用层数上限, 用run_code_with_max_depth可以指定别的上限
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+// 剩余的栈小于RED_ZONE时, 在堆上分配STACK_SIZE大小的新栈继续执行
+const RED_ZONE: usize = 128 * 1024;
+const STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/**
+ * 带全局作用域的第一个帧
+ */
+fn global_frame() -> Rc<RefCell<Frame>> {
     // 创建全局作用域
-    let global_scope: Rc<RefCell<Scope>> = Rc::new(RefCell::new(Scope::Block(ScopeStruct {
-        play_object: HashMap::new(),
-        parent_scope: None,
-    })));
+    let global_scope: Rc<RefCell<Scope>> =
+        Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None))));
     // 建立帧
     let first_frame = Frame {
         parent_frame: None,
         scope: global_scope,
+        depth: 0,
+        max_depth: DEFAULT_MAX_DEPTH,
+        globals: Default::default(),
     };
-    let frame = Rc::new(RefCell::new(first_frame));
-    let asts = parse_file();
-    let mut res = ValType::Boolean(true);
-    for ast in asts {
-        res = match *eval(ast, frame.clone()).unwrap() {
-            ValType::Number(n) => ValType::Number(n),
-            // ValType::String(n) => ValType::String(n),
-            _ => ValType::Boolean(true),
+    Rc::new(RefCell::new(first_frame))
+}
+
+// 执行src/pest/calc.sc, 目前只在测试里调用
+#[cfg_attr(not(test), allow(dead_code))]
+fn main() {
+    let frame = global_frame();
+    // 先检查类型, 有错就不执行
+    let source = read_file();
+    let asts = match parse_code(source.clone()).and_then(|asts| type_check(&asts).map(|_| asts)) {
+        Ok(asts) => asts,
+        Err(e) => {
+            println!("{}", e.render(&source));
+            return;
         }
-    }
+    };
+    let val = match eval_top(asts, frame) {
+        Ok(val) => val,
+        Err(e) => {
+            println!("{}", e.render(&source));
+            return;
+        }
+    };
+    let res = match *val {
+        ValType::Number(n) => ValType::Number(n),
+        // ValType::String(n) => ValType::String(n),
+        _ => ValType::Unit,
+    };
     println!("{:?}", res);
 }
 
+/**
+ * 执行一段代码, 返回最后一条语句的值
+ * 先做类型检查, 有错就不执行
+ */
+pub fn run_code(code: String) -> Result<Rc<ValType>, SimpleError> {
+    run_code_with_max_depth(code, DEFAULT_MAX_DEPTH)
+}
+
+/**
+ * 和run_code一样, 函数调用最多嵌套max_depth层
+ * 超过时返回SimpleError::StackOverflow
+ */
+pub fn run_code_with_max_depth(code: String, max_depth: usize) -> Result<Rc<ValType>, SimpleError> {
+    let frame = global_frame();
+    frame.borrow_mut().max_depth = max_depth;
+    let asts = parse_code(code)?;
+    type_check(&asts)?;
+    eval_top(asts, frame)
+}
+
+#[cfg(test)]
 fn test_entry(code: String) -> ValType {
-    // 创建全局作用域
-    let global_scope: Rc<RefCell<Scope>> = Rc::new(RefCell::new(Scope::Block(ScopeStruct {
-        play_object: HashMap::new(),
-        parent_scope: None,
-    })));
-    // 建立帧
-    let first_frame = Frame {
-        parent_frame: None,
-        scope: global_scope,
+    let frame = global_frame();
+    let asts = parse_code(code.clone()).unwrap_or_else(|e| panic!("{}", e.render(&code)));
+    type_check(&asts).unwrap_or_else(|e| panic!("{}", e.render(&code)));
+    // 最后一条语句的值, 语句本身是Unit
+    let eval_res = &*eval_top(asts, frame).unwrap_or_else(|e| panic!("{}", e.render(&code)));
+    match eval_res {
+        ValType::Unit => ValType::Unit,
+        ValType::Number(n) => ValType::Number(*n),
+        ValType::Long(n) => ValType::Long(*n),
+        ValType::Float(n) => ValType::Float(*n),
+        ValType::Boolean(n) => ValType::Boolean(*n),
+        ValType::String(n) => ValType::String(n.clone()),
+        ValType::Array(items) => ValType::Array(RefCell::new(items.borrow().clone())),
+        ValType::Map(entries) => ValType::Map(RefCell::new(entries.borrow().clone())),
+        ValType::Struct { name, fields } => ValType::Struct {
+            name: name.clone(),
+            fields: RefCell::new(fields.borrow().clone()),
+        },
+        ValType::Enum {
+            name,
+            variant,
+            values,
+        } => ValType::Enum {
+            name: name.clone(),
+            variant: variant.clone(),
+            values: values.clone(),
+        },
+        ValType::Closure { name, .. } => ValType::String(format!("closure {}", name.clone())),
+        // } => ValType::Closure { scope: scope.clone(), block: block.clone(), name: name.clone(), args: vec![] },
+        // ValType::String(n) => ValType::String(n),
+    }
+}
+
+/**
+ * 整数的二元运算, 溢出和除0都报错
+ * int也按i64计算, 再检查是否超出int的范围
+ */
+fn calc_number(op: &str, left: i64, right: i64) -> Result<i64, SimpleError> {
+    let res = match op {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" | "%" if right == 0 => return Err(SimpleError::Calc("除数不能为0".to_string())),
+        "/" => left.checked_div(right),
+        "%" => left.checked_rem(right),
+        _ => return Err(SimpleError::Calc(op.to_string() + " 运算符没有实现")),
     };
-    let frame = Rc::new(RefCell::new(first_frame));
-    let asts = parse_code(code);
-    let mut res = ValType::Boolean(true);
-    for ast in asts {
-        let eval_res = &*eval(ast, frame.clone()).unwrap();
-        res = match eval_res {
-            ValType::Number(n) => ValType::Number(*n),
-            ValType::Boolean(n) => ValType::Boolean(*n),
-            ValType::Closure {
-                scope,
-                block,
+    res.ok_or(SimpleError::Calc("整数溢出".to_string()))
+}
+
+fn to_int(n: i64) -> Result<i32, SimpleError> {
+    i32::try_from(n).map_err(|_| SimpleError::Calc("整数溢出".to_string()))
+}
+
+/**
+ * 浮点数的二元运算, 按IEEE 754, 除0得到inf或者NaN
+ */
+fn calc_float(op: &str, left: f64, right: f64) -> Result<f64, SimpleError> {
+    match op {
+        "+" => Ok(left + right),
+        "-" => Ok(left - right),
+        "*" => Ok(left * right),
+        "/" => Ok(left / right),
+        "%" => Ok(left % right),
+        _ => Err(SimpleError::Calc(op.to_string() + " 运算符没有实现")),
+    }
+}
+
+/**
+ * to_int, to_long, to_float 数字类型之间的显式转换
+ * 浮点数转整数时去掉小数部分, 超出范围报错
+ */
+fn convert_number(name: &str, val: &ValType) -> Result<ValType, SimpleError> {
+    let out_of_range = |target: &str| {
+        let shown = match val {
+            ValType::Long(n) => n.to_string(),
+            ValType::Float(f) => f.to_string(),
+            _ => String::new(),
+        };
+        SimpleError::Calc(format!("{} 超出 {} 的范围", shown, target))
+    };
+    let res = match (name, val) {
+        ("to_int", ValType::Number(n)) => ValType::Number(*n),
+        ("to_int", ValType::Long(n)) => {
+            ValType::Number(i32::try_from(*n).map_err(|_| out_of_range("int"))?)
+        }
+        ("to_int", ValType::Float(f))
+            if *f > i32::MIN as f64 - 1.0 && *f < i32::MAX as f64 + 1.0 =>
+        {
+            ValType::Number(*f as i32)
+        }
+        ("to_long", ValType::Number(n)) => ValType::Long(*n as i64),
+        ("to_long", ValType::Long(n)) => ValType::Long(*n),
+        // i64::MAX转成f64会进位到2^63, 所以上限用 <
+        ("to_long", ValType::Float(f)) if *f >= i64::MIN as f64 && *f < i64::MAX as f64 => {
+            ValType::Long(*f as i64)
+        }
+        ("to_float", ValType::Number(n)) => ValType::Float(*n as f64),
+        ("to_float", ValType::Long(n)) => ValType::Float(*n as f64),
+        ("to_float", ValType::Float(f)) => ValType::Float(*f),
+        ("to_int", ValType::Float(_)) => return Err(out_of_range("int")),
+        ("to_long", ValType::Float(_)) => return Err(out_of_range("long")),
+        _ => {
+            return Err(SimpleError::Calc(format!(
+                "{} 的参数应该是int, long或者float",
+                name
+            )))
+        }
+    };
+    Ok(res)
+}
+
+/**
+ * 预置函数, 没有被同名变量覆盖时才生效
+ */
+pub(crate) fn is_builtin(name: &str) -> bool {
+    matches!(
+        name,
+        "len" | "contains" | "remove" | "keys" | "to_int" | "to_long" | "to_float"
+    )
+}
+
+fn call_builtin(name: &str, params: Vec<Rc<ValType>>) -> Result<Rc<ValType>, SimpleError> {
+    match (name, params.as_slice()) {
+        ("len", [val]) => {
+            let len = match &**val {
+                ValType::Array(items) => items.borrow().len(),
+                ValType::Map(entries) => entries.borrow().len(),
+                ValType::String(s) => s.chars().count(),
+                _ => {
+                    return Err(SimpleError::Calc(
+                        "len 的参数应该是数组或者string".to_string(),
+                    ))
+                }
+            };
+            Ok(Rc::new(ValType::Number(len as i32)))
+        }
+        ("contains", [map, key]) => {
+            let key = map_key(key)?;
+            let res = match &**map {
+                ValType::Map(entries) => entries.borrow().contains_key(&key),
+                _ => return Err(SimpleError::Calc("contains 的参数应该是map".to_string())),
+            };
+            Ok(Rc::new(ValType::Boolean(res)))
+        }
+        // 删除后其他key的顺序不变
+        ("remove", [map, key]) => {
+            let key = map_key(key)?;
+            let res = match &**map {
+                ValType::Map(entries) => entries.borrow_mut().shift_remove(&key).is_some(),
+                _ => return Err(SimpleError::Calc("remove 的参数应该是map".to_string())),
+            };
+            Ok(Rc::new(ValType::Boolean(res)))
+        }
+        ("to_int" | "to_long" | "to_float", [val]) => Ok(Rc::new(convert_number(name, val)?)),
+        // 按插入顺序返回所有key
+        ("keys", [map]) => match &**map {
+            ValType::Map(entries) => {
+                let keys = entries
+                    .borrow()
+                    .keys()
+                    .map(|key| Rc::new(key.to_val()))
+                    .collect();
+                Ok(Rc::new(ValType::Array(RefCell::new(keys))))
+            }
+            _ => Err(SimpleError::Calc("keys 的参数应该是map".to_string())),
+        },
+        _ => Err(SimpleError::Calc(format!(
+            "{} 的参数个数不对, 传了{}个",
+            name,
+            params.len()
+        ))),
+    }
+}
+
+fn map_key(key: &ValType) -> Result<MapKey, SimpleError> {
+    MapKey::from_val(key).ok_or(SimpleError::Calc(
+        "map 的 key 只能是int或者string".to_string(),
+    ))
+}
+
+/**
+ * 检查数组下标, 越界报错
+ */
+fn array_index(index: &ValType, len: usize) -> Result<usize, SimpleError> {
+    match index {
+        ValType::Number(n) if *n >= 0 && (*n as usize) < len => Ok(*n as usize),
+        ValType::Number(n) => Err(SimpleError::Calc(format!(
+            "下标 {} 越界, 数组长度是 {}",
+            n, len
+        ))),
+        _ => Err(SimpleError::Calc("下标不是整数".to_string())),
+    }
+}
+
+/**
+ * 检查参数个数, 把入参的值按顺序绑定到函数的作用域
+ * 没传的参数用默认值, 值的类型要和参数声明的一致
+ */
+fn bind_params(
+    name: &str,
+    params: &[AstNodeType],
+    values: Vec<Rc<ValType>>,
+    frame: Rc<RefCell<Frame>>,
+) -> Result<(), SimpleError> {
+    // 末尾有默认值的参数可以不传
+    let required = params
+        .iter()
+        .rposition(|param| matches!(param, AstNodeType::Declaration { additive: None, .. }))
+        .map_or(0, |index| index + 1);
+    if values.len() < required || values.len() > params.len() {
+        let expected = if required < params.len() {
+            format!("{}到{}", required, params.len())
+        } else {
+            params.len().to_string()
+        };
+        return Err(SimpleError::Calc(format!(
+            "{} 需要{}个参数, 实际传了{}个",
+            name,
+            expected,
+            values.len()
+        )));
+    }
+    let mut values = values.into_iter();
+    for (index, param) in params.iter().enumerate() {
+        let AstNodeType::Declaration {
+            declare_type,
+            identifier,
+            address,
+            additive: default,
+            ..
+        } = param
+        else {
+            continue;
+        };
+        let value = match (values.next(), default) {
+            (Some(value), _) => value,
+            // 默认值在函数的作用域里计算, 可以用前面的参数
+            (None, Some(default)) => eval(default, frame.clone())?,
+            (None, None) => unreachable!("缺少的参数已经报错"),
+        };
+        if !value.is_type(declare_type) {
+            return Err(SimpleError::Calc(format!(
+                "{} 的第{}个参数 {} 应该是 {}, 实际是 {}",
                 name,
-                args: _,
-            } => ValType::String(format!("closure {}", name.clone())),
-            // } => ValType::Closure { scope: scope.clone(), block: block.clone(), name: name.clone(), args: vec![] },
-            // ValType::String(n) => ValType::String(n),
-            _ => ValType::Boolean(true),
+                index + 1,
+                identifier,
+                declare_type,
+                value.type_name()
+            )));
+        }
+        declare(&frame, *address, value);
+    }
+    Ok(())
+}
+
+/**
+ * 声明变量, 放到resolve分配的槽位
+ */
+fn declare(frame: &Rc<RefCell<Frame>>, address: Address, val: Rc<ValType>) {
+    let mut fr = frame.borrow_mut();
+    match address {
+        Address::Local { depth, slot } => {
+            fr.set_slot(depth, slot, val);
+        }
+        Address::Global { slot } => fr.set_global(slot, val),
+        Address::Unresolved => unreachable!("执行前已经resolve"),
+    }
+}
+
+/**
+ * 执行顶层语句, 返回最后一条语句的值, 先解析出变量的位置
+ * 全局变量的槽位表存在frame里, 同一个frame上再执行的语句接着用
+ */
+fn eval_top(
+    mut asts: Vec<AstNodeType>,
+    frame: Rc<RefCell<Frame>>,
+) -> Result<Rc<ValType>, SimpleError> {
+    {
+        let globals = frame.borrow().globals.clone();
+        let mut globals = globals.borrow_mut();
+        for ast in asts.iter_mut() {
+            resolve(ast, &mut globals.names);
+        }
+    }
+    eval_stats(&asts, &frame).map_err(escape_error)
+}
+
+// 只声明函数的语句, 执行语句之前先绑定
+fn is_fn_declaration(stat: &AstNodeType) -> bool {
+    match stat {
+        AstNodeType::Statement { child, .. } => {
+            !child.is_empty()
+                && child
+                    .iter()
+                    .all(|exp| matches!(exp, AstNodeType::FnDeclaration { .. }))
+        }
+        _ => false,
+    }
+}
+
+/**
+ * 按顺序执行块或者顶层的语句, 返回最后一条语句的值
+ * 和类型检查的hoist对应, 先绑定其中声明的函数, 声明之前调用的函数里也能用到后面的函数
+ * 按顺序执行到函数声明时已经绑定过了, 不再重复绑定
+ */
+fn eval_stats(
+    stats: &[AstNodeType],
+    frame: &Rc<RefCell<Frame>>,
+) -> Result<Rc<ValType>, SimpleError> {
+    for stat in stats.iter().filter(|stat| is_fn_declaration(stat)) {
+        eval(stat, frame.clone())?;
+    }
+    let mut val = Rc::new(ValType::Unit);
+    for stat in stats {
+        val = if is_fn_declaration(stat) {
+            Rc::new(ValType::Unit)
+        } else {
+            eval(stat, frame.clone())?
+        };
+    }
+    Ok(val)
+}
+
+/**
+ * break/continue 跑出了顶层或者函数体, 说明不在循环里
+ * return 跑到顶层, 说明不在函数里
+ */
+fn escape_error(e: SimpleError) -> SimpleError {
+    match e {
+        SimpleError::Break | SimpleError::Continue | SimpleError::Return(_) => {
+            SimpleError::Calc(e.to_string())
+        }
+        e => e,
+    }
+}
+
+/**
+ * 函数体的执行结果, 捕获return的值
+ * 没有return时是Unit, 不是最后一条语句的值, 有返回类型的函数要报错
+ */
+fn fn_result(
+    res: Result<Rc<ValType>, SimpleError>,
+    returns: bool,
+) -> Result<Rc<ValType>, SimpleError> {
+    match res {
+        Err(SimpleError::Return(val)) => Ok(val),
+        Ok(_) if returns => Err(SimpleError::Calc(String::from("缺少返回值"))),
+        Ok(_) => Ok(Rc::new(ValType::Unit)),
+        res => res.map_err(escape_error),
+    }
+}
+
+/**
+ * while和for共用的循环
+ * 每次循环执行block都会创建新的作用域
+ */
+fn eval_loop(
+    judge_stat: Option<&AstNodeType>,
+    step: Option<&AstNodeType>,
+    block: &AstNodeType,
+    frame: Rc<RefCell<Frame>>,
+) -> Result<(), SimpleError> {
+    loop {
+        // 没有条件就一直循环
+        if let Some(judge_stat) = judge_stat {
+            match eval(judge_stat, frame.clone())?.as_ref() {
+                ValType::Boolean(true) => {}
+                ValType::Boolean(false) => break,
+                _ => return Err(SimpleError::Calc("循环的条件不是bool".to_string())),
+            }
+        }
+        match eval(block, frame.clone()) {
+            Ok(_) | Err(SimpleError::Continue) => {}
+            Err(SimpleError::Break) => break,
+            Err(e) => return Err(e),
+        }
+        if let Some(step) = step {
+            eval(step, frame.clone())?;
         }
     }
-    res
+    Ok(())
+}
+
+/**
+ * 执行节点, 出错时带上出错节点的位置
+ * 递归很深时自动换到新的栈上, 调用层数由max_depth限制, 不会撑爆宿主的栈
+ */
+fn eval(ast: &AstNodeType, frame: Rc<RefCell<Frame>>) -> Result<Rc<ValType>, SimpleError> {
+    let span = ast.span();
+    stacker::maybe_grow(RED_ZONE, STACK_SIZE, || eval_node(ast, frame))
+        .map_err(|e| e.with_span(span))
 }
 
-fn eval(ast: AstNodeType, frame: Rc<RefCell<Frame>>) -> Result<Rc<ValType>, SimpleError> {
+fn eval_node(ast: &AstNodeType, frame: Rc<RefCell<Frame>>) -> Result<Rc<ValType>, SimpleError> {
     let mut result: Option<Rc<ValType>> = None;
 
     match ast {
-        AstNodeType::Statement { child } => {
-            for exp in *child {
+        AstNodeType::Statement { child, .. } => {
+            for exp in child.iter() {
                 result = Some(eval(exp, frame.clone())?);
             }
         }
 
-        AstNodeType::IntLiteral(val) => {
-            result = Some(Rc::new(ValType::Number(val)));
-        }
-
-        AstNodeType::AdditiveExp { child } => {
-            let mut res: i32 = 0;
-            for child_item in child {
-                let val = &*eval(child_item, frame.clone())?;
-                let eval_res = match val {
-                    ValType::Number(n) => n,
-                    ValType::Closure {
-                        scope,
-                        block,
-                        name,
-                        args,
-                    } => {
-                        return Ok(Rc::new(ValType::Closure {
-                            scope: scope.clone(),
-                            block: block.clone(),
-                            name: name.clone(),
-                            args: vec![],
-                        }));
-                    }
-                    _ => return Err(SimpleError::Calc("Expected number".to_string())),
-                };
-                res = res + eval_res;
-            }
-            result = Some(Rc::new(ValType::Number(res)))
-        }
-
-        AstNodeType::MulitiExp { child } => {
-            let mut start: i32 = 1;
-            for child_item in child {
-                let val = &*eval(child_item, frame.clone())?;
-                let eval_res = match val {
-                    ValType::Number(n) => n,
-                    ValType::Closure {
-                        scope,
-                        block,
-                        name,
-                        args,
-                    } => {
-                        return Ok(Rc::new(ValType::Closure {
-                            scope: scope.clone(),
-                            block: block.clone(),
-                            name: name.clone(),
-                            args: vec![],
-                        }));
-                    }
-                    _ => return Err(SimpleError::Calc("Expected number".to_string())),
-                };
-                start = start * eval_res;
-            }
-            result = Some(Rc::new(ValType::Number(start)));
+        AstNodeType::IntLiteral(val, _) => {
+            result = Some(Rc::new(ValType::Number(*val)));
         }
 
-        AstNodeType::Identifier { ident } => {
-            let var: String = ident.clone();
-            let fr = frame.borrow_mut();
-            if fr.contains_key(&var) {
-                match fr.get(&var) {
-                    Some(res) => {
-                        result = Some(res);
-                    }
-                    None => {
-                        return Err(SimpleError::Calc((var + " key没有值").to_string()));
-                    }
+        AstNodeType::LongLiteral(val, _) => {
+            result = Some(Rc::new(ValType::Long(*val)));
+        }
+
+        AstNodeType::FloatLiteral(val, _) => {
+            result = Some(Rc::new(ValType::Float(*val)));
+        }
+
+        AstNodeType::BoolLiteral(val, _) => {
+            result = Some(Rc::new(ValType::Boolean(*val)));
+        }
+
+        AstNodeType::StringLiteral(val, _) => {
+            result = Some(Rc::new(ValType::String(val.clone())));
+        }
+
+        AstNodeType::BinaryExp {
+            op, left, right, ..
+        } => {
+            let left_val = eval(left, frame.clone())?;
+            let right_val = eval(right, frame.clone())?;
+            let res = match (&*left_val, &*right_val) {
+                (ValType::Number(l), ValType::Number(r)) => {
+                    ValType::Number(to_int(calc_number(op, *l as i64, *r as i64)?)?)
+                }
+                (ValType::Long(l), ValType::Long(r)) => ValType::Long(calc_number(op, *l, *r)?),
+                (ValType::Float(l), ValType::Float(r)) => ValType::Float(calc_float(op, *l, *r)?),
+                // 字符串只能用+拼接
+                (ValType::String(l), ValType::String(r)) if op == "+" => {
+                    ValType::String(format!("{}{}", l, r))
+                }
+                (ValType::String(_), ValType::String(_)) => {
+                    return Err(SimpleError::Calc(op.clone() + " 不能用于字符串"))
+                }
+                (ValType::String(_), ValType::Number(_))
+                | (ValType::Number(_), ValType::String(_)) => {
+                    return Err(SimpleError::Calc(
+                        op.clone() + " 两边不能是字符串和数字混用",
+                    ))
+                }
+                // 不同的数字类型要先显式转换
+                (
+                    ValType::Number(_) | ValType::Long(_) | ValType::Float(_),
+                    ValType::Number(_) | ValType::Long(_) | ValType::Float(_),
+                ) => {
+                    return Err(SimpleError::Calc(
+                        op.clone() + " 两边的数字类型不同, 需要先转换",
+                    ))
                 }
-            } else {
-                return Err(SimpleError::Calc((var + " key没有申明").to_string()));
+                _ => return Err(SimpleError::Calc("Expected number".to_string())),
+            };
+            result = Some(Rc::new(res));
+        }
+
+        AstNodeType::UnaryExp { op, exp, .. } => {
+            let val = eval(exp, frame.clone())?;
+            let res = match (op.as_str(), &*val) {
+                ("-", ValType::Number(n)) => ValType::Number(
+                    n.checked_neg()
+                        .ok_or(SimpleError::Calc("整数溢出".to_string()))?,
+                ),
+                ("-", ValType::Long(n)) => ValType::Long(
+                    n.checked_neg()
+                        .ok_or(SimpleError::Calc("整数溢出".to_string()))?,
+                ),
+                ("-", ValType::Float(f)) => ValType::Float(-f),
+                ("-", _) => return Err(SimpleError::Calc("Expected number".to_string())),
+                ("!", ValType::Boolean(b)) => ValType::Boolean(!b),
+                ("!", _) => return Err(SimpleError::Calc("Expected bool".to_string())),
+                _ => return Err(SimpleError::Calc(op.clone() + " 运算符没有实现")),
+            };
+            result = Some(Rc::new(res));
+        }
+
+        AstNodeType::Identifier { ident, address, .. } => {
+            let fr = frame.borrow();
+            match *address {
+                Address::Local { depth, slot } => match fr.get_slot(depth, slot) {
+                    Some(res) => result = Some(res),
+                    None => return Err(SimpleError::Calc(ident.clone() + " key没有值")),
+                },
+                Address::Global { slot } => match fr.get_global(slot) {
+                    Some(res) => result = Some(res),
+                    None => return Err(SimpleError::Calc(ident.clone() + " key没有申明")),
+                },
+                Address::Unresolved => unreachable!("执行前已经resolve"),
             }
         }
 
-        AstNodeType::JudgeExp { left, right, judge } => {
-            let left_val = eval(*left, frame.clone())?;
-            let right_val = eval(*right, frame.clone())?;
+        AstNodeType::JudgeExp {
+            left, right, judge, ..
+        } => {
+            let left_val = eval(left, frame.clone())?;
+            let right_val = eval(right, frame.clone())?;
+
+            // 只有同类型才能比较, 字符串按字典序, bool只能判断相等
+            match (&*left_val, &*right_val) {
+                (ValType::Number(_), ValType::Number(_)) => {}
+                (ValType::Long(_), ValType::Long(_)) => {}
+                (ValType::Float(_), ValType::Float(_)) => {}
+                (ValType::String(_), ValType::String(_)) => {}
+                (ValType::Boolean(_), ValType::Boolean(_)) if judge == "==" || judge == "!=" => {}
+                _ => return Err(SimpleError::Calc(judge.clone() + " 两边的类型不能比较")),
+            }
 
             match judge.as_str() {
                 ">" => {
@@ -172,224 +622,458 @@ fn eval(ast: AstNodeType, frame: Rc<RefCell<Frame>>) -> Result<Rc<ValType>, Simp
                     let res = *left_val == *right_val;
                     result = Some(Rc::new(ValType::Boolean(res)))
                 }
+                "!=" => {
+                    let res = *left_val != *right_val;
+                    result = Some(Rc::new(ValType::Boolean(res)))
+                }
                 _ => {
-                    return Err(SimpleError::Calc((judge + " 运算符没有实现").to_string()));
+                    return Err(SimpleError::Calc(judge.clone() + " 运算符没有实现"));
                 }
             }
         }
 
+        AstNodeType::LogicExp {
+            op, left, right, ..
+        } => {
+            let left_val = match *eval(left, frame.clone())? {
+                ValType::Boolean(b) => b,
+                _ => return Err(SimpleError::Calc(op.clone() + " 左边不是bool")),
+            };
+            // 短路: && 左边为false, || 左边为true时不再计算右边
+            let res = match (op.as_str(), left_val) {
+                ("&&", false) => false,
+                ("||", true) => true,
+                ("&&", true) | ("||", false) => match *eval(right, frame.clone())? {
+                    ValType::Boolean(b) => b,
+                    _ => return Err(SimpleError::Calc(op.clone() + " 右边不是bool")),
+                },
+                _ => return Err(SimpleError::Calc(op.clone() + " 运算符没有实现")),
+            };
+            result = Some(Rc::new(ValType::Boolean(res)));
+        }
+
         AstNodeType::Declaration {
-            declare_type,
             identifier,
+            address,
+            additive,
+            ..
+        } => {
+            // 类型检查会拦住没有初始值的声明, 这里不检查直接执行时也不能panic
+            let Some(additive) = additive else {
+                return Err(SimpleError::Calc(identifier.clone() + " 声明时没有初始值"));
+            };
+            let var_value = eval(additive, frame.clone())?;
+            declare(&frame, *address, var_value);
+            result = Some(Rc::new(ValType::Unit));
+        }
+
+        AstNodeType::ArrayLiteral { elements, .. } => {
+            let mut items = vec![];
+            for element in elements {
+                items.push(eval(element, frame.clone())?);
+            }
+            result = Some(Rc::new(ValType::Array(RefCell::new(items))));
+        }
+
+        AstNodeType::MapLiteral { entries, .. } => {
+            let mut map = IndexMap::new();
+            for (key, value) in entries {
+                let key = map_key(&*eval(key, frame.clone())?)?;
+                map.insert(key, eval(value, frame.clone())?);
+            }
+            result = Some(Rc::new(ValType::Map(RefCell::new(map))));
+        }
+
+        AstNodeType::IndexExp { target, index, .. } => {
+            let target_val = eval(target, frame.clone())?;
+            let index_val = eval(index, frame.clone())?;
+            match &*target_val {
+                ValType::Array(items) => {
+                    let items = items.borrow();
+                    let index = array_index(&index_val, items.len())?;
+                    result = Some(items[index].clone());
+                }
+                ValType::Map(entries) => {
+                    let key = map_key(&index_val)?;
+                    match entries.borrow().get(&key) {
+                        Some(val) => result = Some(val.clone()),
+                        None => return Err(SimpleError::Calc(format!("map 里没有 key {}", key))),
+                    }
+                }
+                _ => return Err(SimpleError::Calc("不能用下标访问".to_string())),
+            }
+        }
+
+        AstNodeType::IndexAssignment {
+            target,
+            index,
             additive,
+            ..
         } => {
-            let var: String = identifier.clone();
-            let var_value = eval(*additive.unwrap(), frame.clone())?;
-            frame.borrow_mut().set(var, var_value, true);
-            result = Some(Rc::new(ValType::Boolean(true)));
+            let target_val = eval(target, frame.clone())?;
+            let index_val = eval(index, frame.clone())?;
+            let var_value = eval(additive, frame.clone())?;
+            match &*target_val {
+                ValType::Array(items) => {
+                    let mut items = items.borrow_mut();
+                    let index = array_index(&index_val, items.len())?;
+                    items[index] = var_value.clone();
+                    result = Some(var_value);
+                }
+                // 没有的key就插入到最后
+                ValType::Map(entries) => {
+                    let key = map_key(&index_val)?;
+                    entries.borrow_mut().insert(key, var_value.clone());
+                    result = Some(var_value);
+                }
+                _ => return Err(SimpleError::Calc("不能用下标访问".to_string())),
+            }
+        }
+
+        // 结构体的字段类型只在类型检查时用
+        AstNodeType::StructDeclaration { .. } => {
+            result = Some(Rc::new(ValType::Unit));
+        }
+
+        AstNodeType::StructLiteral { name, fields, .. } => {
+            let mut values = IndexMap::new();
+            for (field, value, _) in fields {
+                values.insert(field.clone(), eval(value, frame.clone())?);
+            }
+            result = Some(Rc::new(ValType::Struct {
+                name: name.clone(),
+                fields: RefCell::new(values),
+            }));
+        }
+
+        // 枚举的成员和值的类型只在类型检查时用
+        AstNodeType::EnumDeclaration { .. } => {
+            result = Some(Rc::new(ValType::Unit));
+        }
+
+        AstNodeType::EnumLiteral {
+            name,
+            variant,
+            args,
+            ..
+        } => {
+            let mut values = vec![];
+            for arg in args {
+                values.push(eval(arg, frame.clone())?);
+            }
+            result = Some(Rc::new(ValType::Enum {
+                name: name.clone(),
+                variant: variant.clone(),
+                values,
+            }));
         }
 
-        AstNodeType::AssignmentStatement { ident, additive } => {
-            let var: String = ident.clone();
-            let mut is_contains = false;
+        AstNodeType::MatchStatement { target, arms, .. } => {
+            let target_val = eval(target, frame.clone())?;
+            let (name, variant, values) = match &*target_val {
+                ValType::Enum {
+                    name,
+                    variant,
+                    values,
+                } => (name, variant, values),
+                _ => return Err(SimpleError::Calc("match 的值不是枚举".to_string())),
+            };
+            // 按顺序找第一个匹配的分支, _ 匹配所有成员
+            let arm = arms
+                .iter()
+                .find(|arm| arm.variant.as_ref().is_none_or(|v| v == variant));
+            let arm = match arm {
+                Some(arm) => arm,
+                None => {
+                    return Err(SimpleError::Calc(format!(
+                        "match 没有匹配 {}::{} 的分支",
+                        name, variant
+                    )))
+                }
+            };
+            // 分支自己的作用域, 存放绑定的值
             {
-                // 避免同一时间有可变借用和不可变节用
-                is_contains = frame.borrow().contains_key(&var);
+                let new_scope = Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None))));
+                let mut fr = frame.borrow_mut();
+                fr.create_scope(new_scope);
+                // 绑定的值按顺序放在分支作用域的槽位里
+                for (slot, (_, value)) in arm.bindings.iter().zip(values).enumerate() {
+                    fr.set_slot(0, slot, value.clone());
+                }
             }
-            match is_contains {
-                true => {
-                    let var_value = eval(*additive, frame.clone())?;
-                    let mut fr = frame.borrow_mut();
-                    fr.set(var, var_value.clone(), false);
+            let res = eval(&arm.block, frame.clone());
+            // 销毁作用域
+            frame.borrow_mut().drop_scope();
+            res?;
+            result = Some(Rc::new(ValType::Unit));
+        }
+
+        AstNodeType::FieldExp { target, field, .. } => {
+            let target_val = eval(target, frame.clone())?;
+            match &*target_val {
+                ValType::Struct { name, fields } => match fields.borrow().get(field) {
+                    Some(val) => result = Some(val.clone()),
+                    None => return Err(SimpleError::Calc(format!("{} 没有字段 {}", name, field))),
+                },
+                _ => return Err(SimpleError::Calc(format!("不是结构体, 没有字段 {}", field))),
+            }
+        }
+
+        AstNodeType::FieldAssignment {
+            target,
+            field,
+            additive,
+            ..
+        } => {
+            let target_val = eval(target, frame.clone())?;
+            let var_value = eval(additive, frame.clone())?;
+            match &*target_val {
+                ValType::Struct { name, fields } => {
+                    let mut fields = fields.borrow_mut();
+                    match fields.get_mut(field) {
+                        Some(val) => *val = var_value.clone(),
+                        None => {
+                            return Err(SimpleError::Calc(format!("{} 没有字段 {}", name, field)))
+                        }
+                    }
                     result = Some(var_value);
                 }
-                false => {
-                    return Err(SimpleError::Calc((var + " key没有申明").to_string()));
+                _ => return Err(SimpleError::Calc(format!("不是结构体, 没有字段 {}", field))),
+            }
+        }
+
+        AstNodeType::VarDeclaration {
+            address, additive, ..
+        } => {
+            let var_value = eval(additive, frame.clone())?;
+            declare(&frame, *address, var_value);
+            result = Some(Rc::new(ValType::Unit));
+        }
+
+        AstNodeType::AssignmentStatement {
+            ident,
+            address,
+            additive,
+            ..
+        } => {
+            // 全局变量先确认声明过再计算右边, 局部变量resolve时已经找到了
+            if let Address::Global { slot } = *address {
+                if frame.borrow().get_global(slot).is_none() {
+                    return Err(SimpleError::Calc(ident.clone() + " key没有申明"));
                 }
             }
+            let var_value = eval(additive, frame.clone())?;
+            declare(&frame, *address, var_value.clone());
+            result = Some(var_value);
         }
 
         AstNodeType::IfStatement {
             judge_stat,
             if_stat,
             else_stat,
+            ..
         } => {
-            let judge: bool = match eval(*judge_stat, frame.clone())?.as_ref() {
+            let judge: bool = match eval(judge_stat, frame.clone())?.as_ref() {
                 ValType::Boolean(b) => *b,
-                val => unreachable!("judge返回的不是bool: {:?}", val),
+                _ => return Err(SimpleError::Calc("if的条件不是bool".to_string())),
             };
             if judge {
-                eval(*if_stat, frame.clone())?;
-            } else if else_stat.is_some() {
-                let else_statement = else_stat.unwrap();
-                eval(*else_statement, frame.clone())?;
+                eval(if_stat, frame.clone())?;
+            } else if let Some(else_stat) = else_stat {
+                eval(else_stat, frame.clone())?;
             }
-            result = Some(Rc::new(ValType::Boolean(true)));
+            result = Some(Rc::new(ValType::Unit));
         }
 
-        AstNodeType::ReturnExp { exp } => {
-            match exp {
-                Some(val) => {
-                    result = Some(eval(*val, frame.clone())?);
-                },
-                None => {
-                    result = Some(Rc::new(ValType::Boolean(false)));
-                }
-            }
+        AstNodeType::ReturnExp { exp, .. } => {
+            let val = match exp {
+                Some(val) => eval(val, frame.clone())?,
+                None => Rc::new(ValType::Unit),
+            };
+            // 一路跳出block/if/循环, 直到最近的FnCall
+            return Err(SimpleError::Return(val));
         }
 
-        AstNodeType::Block { statements } => {
+        AstNodeType::Block { statements, .. } => {
             // 创建新的scope
             {
-                let new_scope = Rc::new(RefCell::new(Scope::Block(ScopeStruct {
-                    play_object: HashMap::new(),
-                    parent_scope: None,
-                })));
+                let new_scope = Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None))));
                 let mut fr = frame.borrow_mut();
                 fr.create_scope(new_scope);
             }
 
+            // 出错或者break/continue时, 也要先销毁作用域再往上传
             let val = match statements {
-                Some(stats) => {
-                    let mut val = Rc::new(ValType::Boolean(false));
-                    for stat in stats {
-                        val = eval(stat, frame.clone())?;
-                    }
-                    val
-                }
-                None => {
-                    Rc::new(ValType::Boolean(false))
-                }
+                Some(stats) => eval_stats(stats, &frame),
+                None => Ok(Rc::new(ValType::Unit)),
             };
-            result = Some(val);
             // 销毁作用域
-            let mut fr = frame.borrow_mut();
-            fr.drop_scope();
+            frame.borrow_mut().drop_scope();
+            result = Some(val?);
+        }
+
+        AstNodeType::WhileStatement {
+            judge_stat, block, ..
+        } => {
+            eval_loop(Some(judge_stat), None, block, frame.clone())?;
+            result = Some(Rc::new(ValType::Unit));
+        }
+
+        AstNodeType::ForStatement {
+            init,
+            judge_stat,
+            step,
+            block,
+            ..
+        } => {
+            // for自己的作用域, 存放init里声明的变量
+            {
+                let new_scope = Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None))));
+                let mut fr = frame.borrow_mut();
+                fr.create_scope(new_scope);
+            }
+
+            let res = match init {
+                Some(init) => eval(init, frame.clone()).map(|_| ()),
+                None => Ok(()),
+            }
+            .and_then(|_| eval_loop(judge_stat.as_deref(), step.as_deref(), block, frame.clone()));
+            // 销毁作用域
+            frame.borrow_mut().drop_scope();
+            res?;
+            result = Some(Rc::new(ValType::Unit));
         }
 
+        AstNodeType::BreakStatement { .. } => return Err(SimpleError::Break),
+
+        AstNodeType::ContinueStatement { .. } => return Err(SimpleError::Continue),
+
         AstNodeType::FnCall {
             identifier,
             // 参数列表
             argu_list,
+            span,
         } => {
-            let fn_ident = &*eval(*identifier, frame.clone())?;
+            // 预置函数, 局部变量不会是预置函数
+            if let AstNodeType::Identifier {
+                ident,
+                address: Address::Global { slot },
+                ..
+            } = identifier.as_ref()
+            {
+                if is_builtin(ident) && frame.borrow().get_global(*slot).is_none() {
+                    let mut params = vec![];
+                    for arg in argu_list {
+                        params.push(eval(arg, frame.clone())?);
+                    }
+                    return call_builtin(ident, params);
+                }
+            }
+            let callee_name = match identifier.as_ref() {
+                AstNodeType::Identifier { ident, .. } => ident.clone(),
+                _ => String::from("表达式"),
+            };
+            let fn_ident = &*eval(identifier, frame.clone())?;
             match fn_ident {
                 ValType::Closure {
                     scope,
                     block,
                     name,
                     args,
+                    returns,
                 } => {
-                    // let mut params = Vec::new();
-                    // for arg in argu_list {
-                    //     params.push(eval(arg, frame.clone())?);
-                    // }
+                    // 传值调用, 入参在调用方的作用域里从左到右计算
+                    let mut values = vec![];
+                    for arg in argu_list {
+                        values.push(eval(arg, frame.clone())?);
+                    }
 
-                    // 创建新的frame
-                    let new_frame = Frame {
+                    // 创建新的frame, 直接递归和相互递归都靠层数限制防止无限调用
+                    let (depth, max_depth) = {
+                        let fr = frame.borrow();
+                        (fr.depth + 1, fr.max_depth)
+                    };
+                    // 调用栈里记下这一层的函数名, 调用位置和层数
+                    let trace_frame = TraceFrame {
+                        name: name.clone(),
+                        span: *span,
+                        depth,
+                    };
+                    if depth > max_depth {
+                        return Err(SimpleError::StackOverflow {
+                            max_depth,
+                            trace: vec![trace_frame],
+                        });
+                    }
+                    let new_frame = Rc::new(RefCell::new(Frame {
                         scope: scope.clone(),
                         parent_frame: Some(frame.clone()),
-                    };
-
-                    /**
-                     * TODO
-                     * 这里的实现, 需要入参表达式在新的作用域里执行
-                     */
-                    //  将入参在新的作用域声明(补充block的Declaration节点)
-                    let need_insert_declare = argu_list.len() > 0;
-                    if need_insert_declare {
-                        // 生成statements
-                        let mut statements = vec![];
-                        for (index, arg) in args.iter().enumerate() {
-                            // 得到declare节点
-                            // println!("arg: {:#?}", arg);
-                            // println!("params: {:#?}", argu_list[index]);
-                            match arg {
-                                AstNodeType::Declaration {
-                                    declare_type,
-                                    identifier,
-                                    additive: _,
-                                } => {
-                                    // TODO: additive可能有默认值, 然后parame缺省的情况
-                                    let new_declare = AstNodeType::Declaration {
-                                        declare_type: declare_type.clone(),
-                                        identifier: identifier.clone(),
-                                        additive: Some(Box::new(argu_list[index].clone())),
-                                    };
-                                    statements.push(AstNodeType::Statement {
-                                        child: Box::new(vec![new_declare]),
-                                    });
-                                }
-                                _ => {}
-                            }
-                        }
-
-                        let mut declare_ast = AstNodeType::Block {
-                            statements: Some(statements),
-                        };
-                        let block_statements = match &**block {
-                            AstNodeType::Block { statements } => statements.clone().unwrap(),
-                            _ => panic!("block_statements is not block"),
-                        };
-
-                        // 判断需要合并declare和fncall里面的
-                        match declare_ast {
-                            AstNodeType::Block { ref mut statements } => {
-                                let mut inner_stats = statements.as_mut().unwrap();
-                                inner_stats.extend(block_statements);
-                                // println!("inner_stats: {:#?}", inner_stats);
-                            }
-                            _ => {}
-                        };
-
-                        // 执行block (会创建新的作用域)
-                        let res = eval(declare_ast, Rc::new(RefCell::new(new_frame)));
-                        // println!("block执行结果: {:#?}", res);
-                        result = Some(res?);
-                    } else {
-                        // 执行block
-                        // TODO这里的clone()比较消耗, clone了整个树
-                        let res = eval((**block).clone(), Rc::new(RefCell::new(new_frame)));
-                        result = Some(res?);
+                        depth,
+                        max_depth,
+                        globals: frame.borrow().globals.clone(),
+                    }));
+                    // 参数单独一层作用域, 函数体的block在里面再建一层
+                    {
+                        let new_scope = Rc::new(RefCell::new(Scope::Block(ScopeStruct::new(None))));
+                        new_frame.borrow_mut().create_scope(new_scope);
                     }
 
-                    // 丢掉这个frame(new_frame会默认丢掉)
-                    frame.borrow_mut().parent_frame = None;
-                    // 执行完成后将return语句的值给result
-                    // TODO
+                    bind_params(name, args, values, new_frame.clone())?;
+
+                    // 执行block, 函数体和定义时共用一棵树, 不用复制
+                    let res = eval(block, new_frame);
+                    result = Some(fn_result(res, *returns).map_err(|e| e.push_frame(trace_frame))?);
                 }
-                _ => {
-                    // 函数调用错误
-                    return Err(SimpleError::Calc("函数调用出错".into()));
+                other => {
+                    return Err(SimpleError::Calc(format!(
+                        "{} 是 {}, 不能调用",
+                        callee_name,
+                        other.type_name()
+                    )));
                 }
             }
-            // result = Some(Rc::new(ValType::Boolean(true)));
+            // result = Some(Rc::new(ValType::Unit));
         }
 
         AstNodeType::FnDeclaration {
             return_type,
             identifier,
+            address,
             argu_list,
             block,
+            ..
         } => {
-            let mut fr = frame.borrow_mut();
             let closure = Rc::new(ValType::Closure {
-                scope: fr.scope.clone(),
-                block: Rc::new(*block),
+                scope: frame.borrow().scope.clone(),
+                block: block.clone(),
                 name: identifier.clone(),
-                args: argu_list,
+                args: argu_list.clone(),
+                returns: return_type.is_some(),
             });
-            fr.set(identifier, closure, true);
-            result = Some(Rc::new(ValType::Boolean(true)));
+            declare(&frame, *address, closure);
+            result = Some(Rc::new(ValType::Unit));
         }
 
-        _ => {
-            println!("{:?}", ast);
-            return Err(SimpleError::Calc((" 没处理该表达式").to_string()));
+        AstNodeType::FnExpression {
+            return_type,
+            argu_list,
+            block,
+            ..
+        } => {
+            // 和FnDeclaration一样捕获当前作用域
+            let closure = ValType::Closure {
+                scope: frame.borrow().scope.clone(),
+                block: block.clone(),
+                name: String::from("anonymous"),
+                args: argu_list.clone(),
+                returns: return_type.is_some(),
+            };
+            result = Some(Rc::new(closure));
         }
     }
     Ok(result.unwrap())
 }
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests;
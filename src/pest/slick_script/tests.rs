@@ -4,6 +4,15 @@ use super::*;
 mod tests {
     use super::*;
 
+    // 跳过类型检查直接执行, 返回运行时的错误信息
+    fn eval_unchecked(code: &str) -> String {
+        let frame = global_frame();
+        match eval_top(parse_code(code.to_string()).unwrap(), frame) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("应该报错"),
+        }
+    }
+
     #[test]
     fn test_main() {
         main();
@@ -16,10 +25,7 @@ mod tests {
     a;"#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
-        match res {
-            ValType::Number(n) => assert_eq!(n, 4),
-            _ => {}
-        }
+        assert_eq!(res, ValType::Number(4));
     }
 
     #[test]
@@ -27,10 +33,7 @@ mod tests {
         let code = r#"3>4;"#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
-        match res {
-            ValType::Boolean(n) => assert_eq!(n, false),
-            _ => {}
-        }
+        assert_eq!(res, ValType::Boolean(false));
     }
 
     #[test]
@@ -44,10 +47,7 @@ mod tests {
           a;"#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
-        match res {
-            ValType::Number(n) => assert_eq!(n, 1),
-            _ => {}
-        }
+        assert_eq!(res, ValType::Number(1));
     }
 
     #[test]
@@ -61,10 +61,30 @@ mod tests {
             b;"#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
-        match res {
-            ValType::Number(n) => assert_eq!(n, 0),
-            _ => {}
+        assert_eq!(res, ValType::Number(0));
+    }
+
+    #[test]
+    // 声明, if, 循环和void函数的结果都是Unit
+    fn test_unit() {
+        let statements = [
+            "int a = 1;",
+            "if (true) { 1; }",
+            "while (false) { }",
+            "{ }",
+            "void f () { }",
+            "void f () { 1 + 2; } f();",
+            "void f () { return; } f();",
+        ];
+        for code in statements {
+            assert_eq!(test_entry(code.to_string()), ValType::Unit, "{}", code);
         }
+        // 没有return的函数不会把最后一条语句的值带出来
+        let code = r#"void log (int n) {
+          n * 2;
+        }
+        log(3);"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Unit);
     }
 
     #[test]
@@ -76,10 +96,7 @@ mod tests {
             a;"#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
-        match res {
-            ValType::String(n) => assert_eq!(n, String::from("closure a")),
-            _ => {}
-        }
+        assert_eq!(res, ValType::String(String::from("closure a")));
     }
 
     #[test]
@@ -94,10 +111,7 @@ mod tests {
         b;"#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
-        match res {
-            ValType::String(n) => assert_eq!(n, String::from("closure a")),
-            _ => {}
-        }
+        assert_eq!(res, ValType::String(String::from("closure a")));
     }
 
     #[test]
@@ -110,10 +124,7 @@ mod tests {
         a(1*2);"#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
-        match res {
-            ValType::Number(n) => assert_eq!(n, 2),
-            _ => {}
-        }
+        assert_eq!(res, ValType::Number(2));
     }
 
     #[test]
@@ -126,15 +137,263 @@ mod tests {
         a();"#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
-        match res {
-            ValType::Number(n) => assert_eq!(n, 3),
-            _ => {}
+        assert_eq!(res, ValType::Number(3));
+    }
+
+    #[test]
+    // 没传的参数用默认值, 每次调用时在函数的作用域里计算
+    fn test_default_params() {
+        let code = r#"int base = 1;
+        int add (int a, int b = 10, int c = a + base) {
+          return a + b + c;
         }
+        int first = add(1);
+        base = 100;
+        first * 1000 + add(1, 2) + add(1, 2, 3);"#;
+        // add(1) = 1 + 10 + 2, add(1, 2) = 1 + 2 + 101, add(1, 2, 3) = 6
+        let res = test_entry(code.to_string());
+        assert_eq!(res, ValType::Number(13110));
+    }
+
+    #[test]
+    // 没有类型检查时, 运行时也检查参数个数
+    fn test_default_params_arity() {
+        let message = eval_unchecked("void f (int a, int b = 1) { } f();");
+        assert!(
+            message.starts_with("f 需要1到2个参数, 实际传了0个"),
+            "{}",
+            message
+        );
+        let message = eval_unchecked("void f (int a) { } f(1, 2);");
+        assert!(
+            message.starts_with("f 需要1个参数, 实际传了2个"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    // 没有类型检查时, 运行时检查入参的类型和被调用的值
+    fn test_call_validation() {
+        let message = eval_unchecked("int f (int a, string b) { return a; } f(1, 2);");
+        assert!(
+            message.starts_with("f 的第2个参数 b 应该是 string, 实际是 int"),
+            "{}",
+            message
+        );
+        let message = eval_unchecked("struct P { int x; } void g (P p) { } g(Shape::Circle(1));");
+        assert!(
+            message.starts_with("g 的第1个参数 p 应该是 P, 实际是 Shape"),
+            "{}",
+            message
+        );
+        let message = eval_unchecked("int x = 1; x(2);");
+        assert!(message.starts_with("x 是 int, 不能调用"), "{}", message);
+    }
+
+    #[test]
+    // 跳过类型检查执行没有初始值的声明, 报错而不是panic
+    fn test_declaration_without_initializer() {
+        let message = eval_unchecked("int a; a = 1; a;");
+        assert!(message.starts_with("a 声明时没有初始值"), "{}", message);
+        let message = eval_unchecked("{ int b; }");
+        assert!(message.starts_with("b 声明时没有初始值"), "{}", message);
+        match run_code(String::from("int a; a = 1; a;")) {
+            Err(SimpleError::Type(errors)) => assert_eq!(errors.len(), 1),
+            res => panic!("应该类型检查报错, 实际是 {:?}", res),
+        }
+    }
+
+    #[test]
+    // 没有类型检查时, 有返回类型的函数执行完没有return也要报错
+    fn test_missing_return() {
+        let message = eval_unchecked("int f () { } int x = f(); x + 1;");
+        assert!(message.ends_with("缺少返回值 (位置 21..24)"), "{}", message);
+    }
+
+    #[test]
+    // 入参在调用方计算, 形参和调用方的同名变量互不影响
+    fn test_call_by_value_shadowing() {
+        let code = r#"int x = 5;
+        int inc (int x) {
+          x = x + 1;
+          return x;
+        }
+        inc(x) * 10 + x;"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Number(65));
+        // 实参里的y是调用方块里的y, 不是形参y
+        let code = r#"int y = 1;
+        int pair (int x, int y) {
+          return x * 10 + y;
+        }
+        int res = 0;
+        {
+          int a = 2;
+          int y = 3;
+          res = pair(y, a);
+        }
+        res * 10 + y;"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Number(321));
+    }
+
+    #[test]
+    // 调用方的局部变量可以作为实参, 即使被调用的函数看不到
+    fn test_call_with_caller_locals() {
+        let code = r#"int id (int a) {
+          return a;
+        }
+        int outer () {
+          int local = 7;
+          return id(local);
+        }
+        int total = 0;
+        for (int i = 0; i < 3; i = i + 1) {
+          total = total + id(i);
+        }
+        outer() * 10 + total;"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Number(73));
+    }
+
+    #[test]
+    // 入参从左到右计算
+    fn test_argument_order() {
+        let code = r#"int n = 0;
+        int next () {
+          n = n + 1;
+          return n;
+        }
+        int pair (int a, int b, int c = next()) {
+          return a * 100 + b * 10 + c;
+        }
+        pair(next(), next());"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Number(123));
+    }
+
+    #[test]
+    // 直接递归和相互递归, odd在even后面声明
+    fn test_recursion() {
+        let code = r#"int fib (int n) {
+          if (n < 2) { return n; }
+          return fib(n - 1) + fib(n - 2);
+        }
+        bool even (int n) {
+          if (n == 0) { return true; }
+          return odd(n - 1);
+        }
+        bool odd (int n) {
+          if (n == 0) { return false; }
+          return even(n - 1);
+        }
+        int res = 0;
+        if (even(200) && odd(7)) {
+          res = fib(15);
+        }
+        res;"#;
+        let frame = global_frame();
+        let asts = parse_code(code.to_string()).unwrap();
+        type_check(&asts).unwrap();
+        let res = eval_top(asts, frame).unwrap();
+        assert_eq!(*res, ValType::Number(610));
+    }
+
+    #[test]
+    // 声明之前就调用的函数里, 用到后面声明的函数, 顶层和块里都一样
+    fn test_call_before_hoisted_fn() {
+        let code = r#"int a () { return b(); }
+        int r = a();
+        int b () { return 1; }
+        {
+          int c () { return d(); }
+          r = r + c();
+          int d () { return 2; }
+        }
+        r;"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Number(3));
+    }
+
+    #[test]
+    // 超过调用层数上限时返回错误, 之后还能继续执行
+    fn test_max_depth() {
+        let code = r#"int count = 0;
+        int forever (int n) {
+          count = n;
+          return forever(n + 1);
+        }
+        int down (int n) {
+          if (n == 0) { return 0; }
+          return down(n - 1) + 1;
+        }"#;
+        let frame = global_frame();
+        frame.borrow_mut().max_depth = 50;
+        let run = |code: &str| eval_top(parse_code(code.to_string()).unwrap(), frame.clone());
+        run(code).unwrap();
+        match run("forever(1);") {
+            Err(e @ SimpleError::StackOverflow { max_depth: 50, .. }) => {
+                assert!(e
+                    .to_string()
+                    .ends_with("调用层数超过上限 50, 可能是无限递归"))
+            }
+            _ => panic!("应该超过调用层数上限"),
+        }
+        assert_eq!(*run("count;").unwrap(), ValType::Number(50));
+        assert_eq!(*run("down(49);").unwrap(), ValType::Number(49));
+        assert!(matches!(
+            run("down(50);"),
+            Err(SimpleError::StackOverflow { max_depth: 50, .. })
+        ));
+    }
+
+    #[test]
+    // 通过run_code_with_max_depth指定调用层数上限
+    fn test_run_code_with_max_depth() {
+        let code = |call: &str| {
+            format!(
+                r#"int down (int n) {{
+                  if (n == 0) {{ return 0; }}
+                  return down(n - 1) + 1;
+                }}
+                {}"#,
+                call
+            )
+        };
+        let res = run_code_with_max_depth(code("down(9);"), 10).unwrap();
+        assert_eq!(*res, ValType::Number(9));
+        assert!(matches!(
+            run_code_with_max_depth(code("down(10);"), 10),
+            Err(SimpleError::StackOverflow { max_depth: 10, .. })
+        ));
+        // 默认的上限更大
+        let res = run_code(code("down(10);")).unwrap();
+        assert_eq!(*res, ValType::Number(10));
+    }
+
+    #[test]
+    // 默认的上限以内, 深递归不会撑爆测试线程的栈
+    fn test_deep_recursion() {
+        let code = format!(
+            r#"int down (int n) {{
+              if (n == 0) {{ return 0; }}
+              return down(n - 1) + 1;
+            }}
+            down({});"#,
+            DEFAULT_MAX_DEPTH - 1
+        );
+        let res = test_entry(code);
+        assert_eq!(res, ValType::Number(DEFAULT_MAX_DEPTH as i32 - 1));
+    }
+
+    #[test]
+    // 空的函数体也能调用
+    fn test_empty_fn_body() {
+        let code = r#"void noop (int a, string b = "x") { }
+        noop(1);
+        noop(2, "y");"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Unit);
     }
 
     #[test]
     fn test_closure_call() {
-        let code = r#"int a () { 
+        let code = r#"fn int () a () { 
           int c = 6;
           int b () {
             c = c + 1;
@@ -148,9 +407,691 @@ mod tests {
         "#;
         let res = test_entry(code.to_string());
         // println!("{:#?}", res);
+        assert_eq!(res, ValType::Number(7));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let code = r#"int a = 5 - 3;
+        int b = 7 / 2 + 7 % 3;
+        a * 10 + b - 2 - 1;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 21),
+            _ => panic!("运算结果不是数字"),
+        }
+    }
+
+    #[test]
+    fn test_float_long() {
+        let code = r#"float c = -273.15;
+        float f = c * 9.0 / 5.0 + 32.0;
+        long ts = 1463895090L * 1000L + 123L;
+        f;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Float(f) => assert!((f - -459.67).abs() < 1e-9),
+            _ => panic!("返回值不是浮点数"),
+        }
+        let code = r#"long ts = 1463895090L * 1000L + 123L;
+        ts / 1000L > to_long(1463895089) && -ts < 0L;"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Boolean(true));
+    }
+
+    #[test]
+    // 显式转换, 浮点数转整数去掉小数部分
+    fn test_number_convert() {
+        let cases = [
+            ("to_int(3.9);", ValType::Number(3)),
+            ("to_int(-2.5);", ValType::Number(-2)),
+            ("to_int(42L);", ValType::Number(42)),
+            ("to_long(2147483647) + 1L;", ValType::Long(2147483648)),
+            ("to_long(1e12);", ValType::Long(1000000000000)),
+            ("to_float(7) / 2.0;", ValType::Float(3.5)),
+            ("to_float(1463895090L);", ValType::Float(1463895090.0)),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(test_entry(code.to_string()), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "3000000000 超出 int 的范围")]
+    fn test_number_convert_out_of_range() {
+        test_entry(String::from("to_int(3000000000L);"));
+    }
+
+    #[test]
+    #[should_panic(expected = "整数溢出")]
+    fn test_long_overflow() {
+        test_entry(String::from("long a = 9223372036854775807L; a + 1L;"));
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        let code = r#"int a = 3;
+        -(a + 2) * 2 - -a;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, -7),
+            _ => panic!("运算结果不是数字"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "除数不能为0")]
+    fn test_divide_zero() {
+        let code = r#"int a = 0;
+        5 / a;"#;
+        test_entry(code.to_string());
+    }
+
+    #[test]
+    fn test_compare() {
+        let code = r#"int a = 3;
+        a >= 3 && a <= 3 && a != 4 && !(a == 4) && true == !false;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Boolean(b) => assert!(b),
+            _ => panic!("比较结果不是bool"),
+        }
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let code = r#"int x = 0;
+        x != 0 && 10 / x > 1 || x == 0;"#;
+        let res = test_entry(code.to_string());
         match res {
-            ValType::Number(n) => assert_eq!(n, 7),
-            _ => {}
+            ValType::Boolean(b) => assert!(b),
+            _ => panic!("逻辑运算结果不是bool"),
+        }
+    }
+
+    #[test]
+    fn test_if_logic() {
+        let code = r#"int a = 0;
+        if (a < 1 || 1 / a > 0) {
+          a = 5;
+        }
+        a;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 5),
+            _ => panic!("运算结果不是数字"),
+        }
+    }
+
+    #[test]
+    fn test_string() {
+        let code = r#"string a = "hello";
+        string b = a + ", " + "z\"lang\"";
+        b;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::String(s) => assert_eq!(s, "hello, z\"lang\""),
+            _ => panic!("结果不是字符串"),
+        }
+    }
+
+    #[test]
+    fn test_string_compare() {
+        let code = r#"string a = "apple";
+        a == "apple" && a != "pear" && a < "banana" && "b" >= "abc";"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Boolean(b) => assert!(b),
+            _ => panic!("比较结果不是bool"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "字符串和数字混用")]
+    fn test_string_mix_number() {
+        let code = r#"string a = "1";
+        a + 1;"#;
+        // 跳过类型检查, 运行时也要报错
+        let frame = global_frame();
+        eval_top(parse_code(code.to_string()).unwrap(), frame).unwrap();
+    }
+
+    #[test]
+    fn test_while() {
+        let code = r#"int i = 0;
+        int sum = 0;
+        while (i < 5) {
+          i = i + 1;
+          sum = sum + i;
+        }
+        sum;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 15),
+            _ => panic!("运算结果不是数字"),
+        }
+    }
+
+    #[test]
+    fn test_for_break_continue() {
+        let code = r#"int sum = 0;
+        for (int i = 0; i < 100; i = i + 1) {
+          if (i % 2 == 0) {
+            continue;
+          }
+          if (i > 9) {
+            break;
+          }
+          sum = sum + i;
+        }
+        sum;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 25),
+            _ => panic!("运算结果不是数字"),
+        }
+    }
+
+    #[test]
+    // break只跳出最内层循环, 跳出时作用域也要销毁
+    fn test_nested_loop_scope() {
+        let code = r#"int count = 0;
+        int i = 100;
+        for (int i = 0; i < 3; i = i + 1) {
+          int j = 0;
+          while (true) {
+            int k = j;
+            {
+              if (k == 2) {
+                break;
+              }
+            }
+            j = j + 1;
+            count = count + 1;
+          }
+        }
+        count * 1000 + i;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 6100),
+            _ => panic!("运算结果不是数字"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "break 只能在循环里使用")]
+    fn test_break_outside_loop() {
+        let code = r#"int a () {
+          break;
+        }
+        while (true) {
+          a();
+        }"#;
+        test_entry(code.to_string());
+    }
+
+    #[test]
+    fn test_return_early() {
+        let code = r#"int f () {
+          return 1;
+          2;
+        }
+        f();"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 1),
+            _ => panic!("返回值不是数字"),
+        }
+    }
+
+    #[test]
+    // return 从if和循环里直接跳出函数
+    fn test_return_nested() {
+        let code = r#"int find (int target) {
+          int i = 0;
+          while (true) {
+            {
+              if (i * i >= target) {
+                return i;
+              }
+            }
+            i = i + 1;
+          }
+          return -1;
+        }
+        int a = 7;
+        find(50) * 100 + a;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 807),
+            _ => panic!("返回值不是数字"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "return 只能在函数里使用")]
+    fn test_return_top_level() {
+        let code = r#"int a = 1;
+        return a;"#;
+        test_entry(code.to_string());
+    }
+
+    #[test]
+    // 运行时错误带上出错节点的位置
+    fn test_runtime_error_span() {
+        let code = String::from("int a = 1;\nint b () {\n  return a + c;\n}\nb();");
+        let asts = parse_code(code.clone()).unwrap();
+        let frame = global_frame();
+        match eval_top(asts, frame) {
+            Err(SimpleError::Runtime { message, span, .. }) => {
+                assert_eq!(message, "c key没有申明");
+                assert_eq!(&code[span.start..span.end], "c");
+                assert_eq!(span.line_col(&code), (3, 14));
+            }
+            _ => panic!("应该报错"),
+        }
+    }
+
+    #[test]
+    // 局部变量按resolve的槽位读写: 遮蔽, 闭包捕获, 块里函数的相互递归
+    fn test_resolved_locals() {
+        let code = r#"int outer () {
+          int x = 1;
+          int add (int y) { return x + y; }
+          int inner = 0;
+          {
+            int x = 10;
+            x = x + add(x);
+            bool even (int n) {
+              if (n == 0) { return true; }
+              return odd(n - 1);
+            }
+            bool odd (int n) {
+              if (n == 0) { return false; }
+              return even(n - 1);
+            }
+            if (even(4)) { inner = x + 1; }
+          }
+          x = x + 1;
+          return inner * 100 + add(x);
+        }
+        outer();"#;
+        assert_eq!(test_entry(code.to_string()), ValType::Number(2204));
+    }
+
+    #[test]
+    // 运行时错误带上调用栈, 外层在前, 每层是函数名, 执行到的位置和层数
+    fn test_traceback() {
+        let code = String::from(
+            "int inner (int n) {\n  return 10 / n;\n}\nint outer (int n) {\n  return inner(n - 1);\n}\nint a = outer(1);",
+        );
+        let frame = global_frame();
+        let e = eval_top(parse_code(code.clone()).unwrap(), frame.clone()).unwrap_err();
+        match &e {
+            SimpleError::Runtime { trace, .. } => {
+                let names: Vec<(&str, usize)> =
+                    trace.iter().map(|f| (f.name.as_str(), f.depth)).collect();
+                assert_eq!(names, vec![("inner", 2), ("outer", 1)]);
+            }
+            _ => panic!("应该是运行时错误"),
+        }
+        assert_eq!(
+            e.render(&code),
+            "调用栈 (最近的调用在最后):
+  [0] <顶层>, 第7行第9列
+  [1] outer, 第5行第10列
+  [2] inner, 第2行第10列
+运行时错误: 除数不能为0 (第2行第10列)"
+        );
+        // 没有经过函数调用的错误没有调用栈
+        let e = eval_top(parse_code(String::from("1 / 0;")).unwrap(), frame).unwrap_err();
+        assert_eq!(e.render("1 / 0;"), "运行时错误: 除数不能为0 (第1行第1列)");
+    }
+
+    #[test]
+    // 调用本身出的错(比如缺少返回值)也要记下最里面这一层
+    fn test_traceback_call_error() {
+        let code = String::from(
+            "int inner () {\n}\nint outer () {\n  return 10 / inner();\n}\nint a = outer();",
+        );
+        let frame = global_frame();
+        assert_eq!(
+            eval_top(parse_code(code.clone()).unwrap(), frame)
+                .unwrap_err()
+                .render(&code),
+            "调用栈 (最近的调用在最后):
+  [0] <顶层>, 第6行第9列
+  [1] outer, 第4行第15列
+  [2] inner, 第4行第15列
+运行时错误: 缺少返回值 (第4行第15列)"
+        );
+    }
+
+    #[test]
+    // 无限递归的调用栈里, 连续重复的层只显示前几层
+    fn test_traceback_recursion() {
+        let code = String::from("int forever (int n) {\n  return forever(n + 1);\n}\nforever(1);");
+        let res = run_code_with_max_depth(code.clone(), 20);
+        assert_eq!(
+            res.unwrap_err().render(&code),
+            "调用栈 (最近的调用在最后):
+  [0] <顶层>, 第4行第1列
+  [1] forever, 第2行第10列
+  [2] forever, 第2行第10列
+  [3] forever, 第2行第10列
+  ... 上一行又重复了 17 次
+  [21] forever
+调用层数超过上限 20, 可能是无限递归"
+        );
+    }
+
+    #[test]
+    fn test_bool_string_declare() {
+        let code = r#"bool ok = 1 < 2;
+        string name = "z";
+        bool is_z (string s, bool strict) {
+          return strict && s == "z";
+        }
+        fn bool (string, bool) check = is_z;
+        if (ok && check(name, true)) {
+          name = name + "-lang";
+        }
+        name;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::String(s) => assert_eq!(s, "z-lang"),
+            _ => panic!("返回值不是字符串"),
+        }
+    }
+
+    #[test]
+    fn test_var_declare() {
+        let code = r#"fn int (int) make_adder (int n) {
+          return fn int (int x) { return x + n; };
+        }
+        var add2 = make_adder(2);
+        let total = 0;
+        for (var i = 0; i < 4; i = i + 1) {
+          total = add2(total);
+        }
+        total;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 8),
+            _ => panic!("返回值不是数字"),
+        }
+    }
+
+    #[test]
+    fn test_array() {
+        let code = r#"int[] a = [1, 2, 3];
+        int[][] grid = [[1, 2], [3, 4]];
+        a[0] = a[1] + a[2];
+        grid[1][0] = 10;
+        int sum = 0;
+        for (int i = 0; i < len(a); i = i + 1) {
+          sum = sum + a[i];
+        }
+        sum * 100 + grid[1][0] + len("abc");"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 1013),
+            _ => panic!("返回值不是数字"),
+        }
+    }
+
+    #[test]
+    // 数组是引用, 赋值和传参都共享同一个数组
+    fn test_array_reference() {
+        let code = r#"int[] a = [1, 2];
+        var b = a;
+        void set_first (int[] arr) {
+          arr[0] = 9;
+        }
+        set_first(b);
+        b[1] = 8;
+        a;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Array(items) => {
+                let items: Vec<i32> = items
+                    .borrow()
+                    .iter()
+                    .map(|item| match **item {
+                        ValType::Number(n) => n,
+                        _ => panic!("元素不是数字"),
+                    })
+                    .collect();
+                assert_eq!(items, vec![9, 8]);
+            }
+            _ => panic!("返回值不是数组"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "下标 3 越界, 数组长度是 3")]
+    fn test_array_out_of_bounds() {
+        let code = r#"int[] a = [1, 2, 3];
+        a[len(a)] = 4;"#;
+        test_entry(code.to_string());
+    }
+
+    #[test]
+    fn test_map() {
+        let code = r#"map<string, int> ports = {"http": 80, "ssh": 22};
+        ports["https"] = 443;
+        ports["http"] = 8080;
+        remove(ports, "ssh");
+        remove(ports, "ftp");
+        string order = "";
+        string[] names = keys(ports);
+        for (int i = 0; i < len(names); i = i + 1) {
+          order = order + names[i] + ",";
+        }
+        if (contains(ports, "https") && !contains(ports, "ssh") && ports["http"] == 8080) {
+          order = order + "ok";
+        }"#;
+        let frame = global_frame();
+        let asts = parse_code(code.to_string()).unwrap();
+        type_check(&asts).unwrap();
+        eval_top(asts, frame.clone()).unwrap();
+        // 删除不改变顺序, 修改已有的key不改变顺序
+        let slot = frame.borrow().globals.borrow().names["order"];
+        let order = frame.borrow().get_global(slot).unwrap();
+        assert_eq!(*order, ValType::String(String::from("http,https,ok")));
+    }
+
+    #[test]
+    fn test_map_int_key() {
+        let code = r#"map<int, string> names = {};
+        names[2] = "b";
+        names[1] = "a";
+        var m = names;
+        m[3] = "c";
+        names[1] + names[2] + names[3];"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::String(s) => assert_eq!(s, "abc"),
+            _ => panic!("返回值不是字符串"),
+        }
+    }
+
+    #[test]
+    fn test_struct() {
+        let code = r#"struct Point {
+          int x;
+          int y;
+        }
+        struct Line {
+          Point from;
+          Point to;
+          string name;
+        }
+        int length (Line l) {
+          return l.to.x - l.from.x + l.to.y - l.from.y;
+        }
+        Point a = Point { x: 1, y: 2 };
+        var line = Line { name: "l", from: a, to: Point { x: 4, y: 6 } };
+        line.to.y = 10;
+        a.x = 0;
+        length(line);"#;
+        let res = test_entry(code.to_string());
+        assert_eq!(res, ValType::Number(12));
+    }
+
+    #[test]
+    // 结构体是引用, 放进数组和传参都共享同一个
+    fn test_struct_reference() {
+        let code = r#"struct Counter {
+          int n;
+        }
+        void inc (Counter c) {
+          c.n = c.n + 1;
+        }
+        Counter c = Counter { n: 0 };
+        Counter[] all = [c, c];
+        inc(all[0]);
+        all[1].n = all[1].n + 10;
+        c;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Struct { name, fields } => {
+                assert_eq!(name, "Counter");
+                assert_eq!(*fields.borrow()["n"], ValType::Number(11));
+            }
+            _ => panic!("返回值不是结构体"),
+        }
+    }
+
+    #[test]
+    fn test_enum_match() {
+        let code = r#"enum Shape {
+          Circle(int),
+          Rect(int, int),
+          Empty
+        }
+        int area (Shape s) {
+          int res = 0;
+          match (s) {
+            Circle(r) => { res = 3 * r * r; }
+            Rect(w, h) => { res = w * h; }
+            Empty => { res = 0; }
+          }
+          return res;
+        }
+        Shape[] shapes = [Shape::Circle(2), Shape::Rect(3, 4), Shape::Empty];
+        area(shapes[0]) + area(shapes[1]) + area(shapes[2]);"#;
+        let res = test_entry(code.to_string());
+        assert_eq!(res, ValType::Number(24));
+    }
+
+    #[test]
+    // 每个分支有自己的作用域, 绑定的变量覆盖外层, 出了分支就没有了
+    fn test_match_arm_scope() {
+        let code = r#"enum Option {
+          Some(int),
+          None
+        }
+        int v = 1;
+        int seen = 0;
+        match (Option::Some(5)) {
+          Some(v) => { seen = v; v = 6; }
+          _ => { seen = -1; }
+        }
+        match (Option::None) {
+          Some(v) => { seen = seen + v; }
+          _ => { seen = seen + 10; }
+        }
+        seen * 10 + v;"#;
+        let res = test_entry(code.to_string());
+        assert_eq!(res, ValType::Number(151));
+        // 没有类型检查时, 运行时也会发现没有匹配的分支
+        let code = r#"enum Color { Red, Green }
+        match (Color::Green) {
+          Red => { }
+        }"#;
+        assert!(eval_unchecked(code).starts_with("match 没有匹配 Color::Green 的分支"));
+    }
+
+    #[test]
+    #[should_panic(expected = "map 里没有 key \"b\"")]
+    fn test_map_missing_key() {
+        let code = r#"map<string, int> m = {"a": 1};
+        m["b"];"#;
+        test_entry(code.to_string());
+    }
+
+    #[test]
+    // 匿名函数作为初始值、参数和返回值
+    fn test_fn_expression() {
+        let code = r#"fn int (int) double = fn int (int x) { return x * 2; };
+        int apply (fn int (int) f) {
+          return f(5);
+        }
+        fn int (int) make_adder (int n) {
+          return fn int (int x) { return x + n; };
+        }
+        fn int (int) add3 = make_adder(3);
+        apply(double) + apply(fn int (int y) { return y - 1; }) + add3(4);"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 21),
+            _ => panic!("返回值不是数字"),
+        }
+    }
+
+    #[test]
+    // 匿名函数捕获的是作用域, 之后的修改也能看到
+    fn test_fn_expression_capture() {
+        let code = r#"int count = 0;
+        fn void () inc = fn void () { count = count + 1; };
+        inc();
+        inc();
+        count;"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 2),
+            _ => panic!("返回值不是数字"),
+        }
+    }
+
+    #[test]
+    // 任意表达式后面都可以调用
+    fn test_postfix_call() {
+        let code = r#"int c = 2;
+        fn int () a () {
+          int b = c;
+          int d () {
+            return b;
+          }
+          return d;
+        }
+        fn int (int) make_adder (int n) {
+          return fn int (int x) { return x + n; };
+        }
+        fn int (int) neg = fn int (int x) { return -x; };
+        a()() * 100 + make_adder(10)(3) + (neg)(1) + fn int () { return 5; }();"#;
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 217),
+            _ => panic!("返回值不是数字"),
+        }
+    }
+
+    #[test]
+    // Windows 编辑器写的脚本: CRLF换行, tab缩进, 注释
+    fn test_windows_script() {
+        let code = "/*\r\n * 求和\r\n */\r\nint sum (int n) {\r\n\tint s = 0; // 累加\r\n\tfor (int i = 1; i <= n; i = i + 1) {\r\n\t\ts = s + i;\r\n\t}\r\n\treturn s;\r\n}\r\n\r\nsum(10);\r\n";
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 55),
+            _ => panic!("返回值不是数字"),
+        }
+        // LF 和 CRLF 混用
+        let code = "int a = 1;\nint b = 2;\r\n\ta + /* 中间的注释 */ b;\n";
+        let res = test_entry(code.to_string());
+        match res {
+            ValType::Number(n) => assert_eq!(n, 3),
+            _ => panic!("返回值不是数字"),
         }
     }
 }
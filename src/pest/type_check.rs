@@ -0,0 +1,1262 @@
+use std::collections::HashMap;
+
+use crate::util::{SimpleError, TypeError};
+
+use super::parse_ast::{AstNodeType, DeclareType, MatchArm, Span};
+use super::slick_script::is_builtin;
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod tests;
+
+/**
+ * 执行前的静态类型检查
+ * 检查赋值, 带初始值的声明, 函数调用的入参和返回值
+ * 不在第一个错误停下, 所有错误一起返回
+ */
+pub fn type_check(asts: &[AstNodeType]) -> Result<(), SimpleError> {
+    let mut checker = TypeChecker::new();
+    checker.hoist(asts);
+    for ast in asts {
+        checker.check(ast);
+    }
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(SimpleError::Type(checker.errors))
+    }
+}
+
+/**
+ * 函数的类型, 由返回类型和形参列表得到
+ */
+fn fn_type(return_type: &Option<Box<DeclareType>>, argu_list: &[AstNodeType]) -> DeclareType {
+    let mut list_vec = vec![];
+    for argu in argu_list {
+        if let AstNodeType::Declaration { declare_type, .. } = argu {
+            list_vec.push(declare_type.clone());
+        }
+    }
+    DeclareType::FnType {
+        return_type: return_type.clone(),
+        argu_list: Box::new(list_vec),
+    }
+}
+
+// 末尾有默认值的参数个数, 调用时可以省略
+fn default_count(argu_list: &[AstNodeType]) -> usize {
+    argu_list
+        .iter()
+        .rev()
+        .take_while(|argu| {
+            matches!(
+                argu,
+                AstNodeType::Declaration {
+                    additive: Some(_),
+                    ..
+                }
+            )
+        })
+        .count()
+}
+
+/**
+ * 空数组和空map推导不出类型, 只能赋给数组和map
+ * 和要求的类型对不上时返回字面量的说法
+ */
+fn empty_literal_mismatch(ast: &AstNodeType, expected: &DeclareType) -> Option<&'static str> {
+    match (ast, expected) {
+        (AstNodeType::ArrayLiteral { elements, .. }, DeclareType::Array(_))
+            if elements.is_empty() =>
+        {
+            None
+        }
+        (AstNodeType::ArrayLiteral { elements, .. }, _) if elements.is_empty() => Some("空数组"),
+        (AstNodeType::MapLiteral { entries, .. }, DeclareType::Map { .. })
+            if entries.is_empty() =>
+        {
+            None
+        }
+        (AstNodeType::MapLiteral { entries, .. }, _) if entries.is_empty() => Some("空 map"),
+        _ => None,
+    }
+}
+
+/**
+ * 执行完这条语句是不是一定遇到了return
+ * 循环不看条件, 当作可能一次都不执行
+ */
+fn always_returns(ast: &AstNodeType) -> bool {
+    match ast {
+        AstNodeType::ReturnExp { .. } => true,
+        AstNodeType::Statement { child, .. } => child.iter().any(always_returns),
+        AstNodeType::Block {
+            statements: Some(stats),
+            ..
+        } => stats.iter().any(always_returns),
+        AstNodeType::IfStatement {
+            if_stat,
+            else_stat: Some(else_stat),
+            ..
+        } => always_returns(if_stat) && always_returns(else_stat),
+        AstNodeType::MatchStatement { arms, .. } => {
+            !arms.is_empty() && arms.iter().all(|arm| always_returns(&arm.block))
+        }
+        _ => false,
+    }
+}
+
+fn is_number(declare_type: &DeclareType) -> bool {
+    matches!(
+        declare_type,
+        DeclareType::Int | DeclareType::Long | DeclareType::Float
+    )
+}
+
+/**
+ * 作用域里记录的变量
+ */
+#[derive(Clone)]
+struct Symbol {
+    // None表示类型未知, 初始值已经报过错
+    declare_type: Option<DeclareType>,
+    // var/let声明, 类型由初始值推导
+    inferred: bool,
+    // 函数声明里有默认值的参数个数
+    defaults: usize,
+    // 提前登记的函数, 见hoist
+    hoisted: bool,
+}
+
+struct TypeChecker {
+    // 作用域链, 最后一个是当前作用域, 和运行时的Scope一一对应
+    scopes: Vec<HashMap<String, Symbol>>,
+    // 正在检查的函数的返回类型, 里面的None是void, 栈为空说明在顶层
+    fn_returns: Vec<Option<DeclareType>>,
+    // 进入函数体时作用域的层数, 和fn_returns一一对应
+    fn_depths: Vec<usize>,
+    // 包着当前语句的循环层数, 进入函数体时从0开始
+    loops: usize,
+    // 声明过的结构体, 字段的类型和名字
+    structs: HashMap<String, Vec<(DeclareType, String)>>,
+    // 声明过的枚举, 成员名和成员带的值的类型
+    enums: HashMap<String, Vec<(String, Vec<DeclareType>)>>,
+    errors: Vec<TypeError>,
+}
+
+impl TypeChecker {
+    fn new() -> Self {
+        TypeChecker {
+            scopes: vec![HashMap::new()],
+            fn_returns: vec![],
+            fn_depths: vec![],
+            loops: 0,
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            errors: vec![],
+        }
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.errors.push(TypeError { message, span });
+    }
+
+    fn declare(&mut self, ident: &str, declare_type: DeclareType) {
+        self.declare_symbol(
+            ident,
+            Symbol {
+                declare_type: Some(declare_type),
+                inferred: false,
+                defaults: 0,
+                hoisted: false,
+            },
+        );
+    }
+
+    fn declare_symbol(&mut self, ident: &str, symbol: Symbol) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(ident.to_string(), symbol);
+    }
+
+    // 从里往外找变量
+    // 提前登记的函数只在外层作用域的函数体里可见
+    fn lookup(&self, ident: &str) -> Option<Symbol> {
+        let fn_depth = self.fn_depths.last().copied().unwrap_or(0);
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, scope)| {
+                scope
+                    .get(ident)
+                    .filter(|symbol| !symbol.hoisted || index < fn_depth)
+            })
+            .cloned()
+    }
+
+    /**
+     * 提前登记块里声明的函数, 函数体里可以调用后面才声明的函数, 支持相互递归
+     * 运行时执行块里的语句之前, 块里所有的函数已经绑定好了, 见eval_stats
+     * 函数体外面, 声明之前还是不能用
+     */
+    fn hoist(&mut self, stats: &[AstNodeType]) {
+        for stat in stats {
+            let AstNodeType::Statement { child, .. } = stat else {
+                continue;
+            };
+            for exp in child.iter() {
+                if let AstNodeType::FnDeclaration {
+                    return_type,
+                    identifier,
+                    argu_list,
+                    ..
+                } = exp
+                {
+                    let symbol = Symbol {
+                        declare_type: Some(fn_type(return_type, argu_list)),
+                        inferred: false,
+                        defaults: default_count(argu_list),
+                        hoisted: true,
+                    };
+                    self.scopes
+                        .last_mut()
+                        .unwrap()
+                        .entry(identifier.clone())
+                        .or_insert(symbol);
+                }
+            }
+        }
+    }
+
+    // 表达式是推导出类型的变量时, 在错误信息里补充推导出的类型
+    fn inferred_note(&self, ast: &AstNodeType) -> String {
+        if let AstNodeType::Identifier { ident, .. } = ast {
+            if let Some(Symbol {
+                declare_type: Some(declare_type),
+                inferred: true,
+                ..
+            }) = self.lookup(ident)
+            {
+                return format!(", {} 的类型由初始值推导为 {}", ident, declare_type);
+            }
+        }
+        String::new()
+    }
+
+    /**
+     * 检查语句, 表达式交给check_exp
+     */
+    fn check(&mut self, ast: &AstNodeType) {
+        match ast {
+            AstNodeType::Statement { child, .. } => {
+                for exp in child.iter() {
+                    self.check(exp);
+                }
+            }
+
+            AstNodeType::Declaration {
+                declare_type,
+                identifier,
+                additive,
+                span,
+                ..
+            } => {
+                self.check_declare_type(declare_type, *span);
+                if let Some(additive) = additive {
+                    let usage = format!("作为 {} 的初始值", identifier);
+                    if let Some(literal) = empty_literal_mismatch(additive, declare_type) {
+                        self.error(
+                            format!(
+                                "{} 声明为 {}, 初始值是{}",
+                                identifier, declare_type, literal
+                            ),
+                            *span,
+                        );
+                    } else if let Some(actual) = self.check_value(additive, &usage) {
+                        if actual != *declare_type {
+                            self.error(
+                                format!(
+                                    "{} 声明为 {}, 初始值是 {}",
+                                    identifier, declare_type, actual
+                                ),
+                                *span,
+                            );
+                        }
+                    }
+                } else {
+                    // 没有初始值的变量读出来没有值, 参数的在check_fn_body里单独声明
+                    self.error(format!("{} 声明时没有初始值", identifier), *span);
+                }
+                self.declare(identifier, declare_type.clone());
+            }
+
+            AstNodeType::VarDeclaration {
+                identifier,
+                additive,
+                span,
+                ..
+            } => {
+                match additive.as_ref() {
+                    AstNodeType::ArrayLiteral { elements, .. } if elements.is_empty() => {
+                        self.error(format!("无法从空数组推导 {} 的类型", identifier), *span)
+                    }
+                    AstNodeType::MapLiteral { entries, .. } if entries.is_empty() => {
+                        self.error(format!("无法从空 map 推导 {} 的类型", identifier), *span)
+                    }
+                    _ => {}
+                }
+                let usage = format!("作为 {} 的初始值", identifier);
+                let declare_type = self.check_value(additive, &usage);
+                self.declare_symbol(
+                    identifier,
+                    Symbol {
+                        declare_type,
+                        inferred: true,
+                        defaults: 0,
+                        hoisted: false,
+                    },
+                );
+            }
+
+            AstNodeType::IfStatement {
+                judge_stat,
+                if_stat,
+                else_stat,
+                ..
+            } => {
+                self.check_cond(judge_stat, "if");
+                self.check(if_stat);
+                if let Some(else_stat) = else_stat {
+                    self.check(else_stat);
+                }
+            }
+
+            AstNodeType::Block { statements, .. } => {
+                self.scopes.push(HashMap::new());
+                self.hoist(statements.as_deref().unwrap_or_default());
+                for stat in statements.iter().flatten() {
+                    self.check(stat);
+                }
+                self.scopes.pop();
+            }
+
+            AstNodeType::WhileStatement {
+                judge_stat, block, ..
+            } => {
+                self.check_cond(judge_stat, "while");
+                self.loops += 1;
+                self.check(block);
+                self.loops -= 1;
+            }
+
+            AstNodeType::ForStatement {
+                init,
+                judge_stat,
+                step,
+                block,
+                ..
+            } => {
+                // 和运行时一样, init里声明的变量在for自己的作用域
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.check(init);
+                }
+                if let Some(judge_stat) = judge_stat {
+                    self.check_cond(judge_stat, "for");
+                }
+                if let Some(step) = step {
+                    self.check(step);
+                }
+                self.loops += 1;
+                self.check(block);
+                self.loops -= 1;
+                self.scopes.pop();
+            }
+
+            AstNodeType::BreakStatement { span } if self.loops == 0 => {
+                self.error("break 只能在循环里使用".to_string(), *span)
+            }
+            AstNodeType::ContinueStatement { span } if self.loops == 0 => {
+                self.error("continue 只能在循环里使用".to_string(), *span)
+            }
+            AstNodeType::BreakStatement { .. } | AstNodeType::ContinueStatement { .. } => {}
+
+            AstNodeType::StructDeclaration { name, fields, span } => {
+                if self.structs.contains_key(name) || self.enums.contains_key(name) {
+                    self.error(format!("{} 已经声明过了", name), *span);
+                    return;
+                }
+                let mut names: Vec<&String> = vec![];
+                for (_, field) in fields {
+                    if names.contains(&field) {
+                        self.error(format!("{} 的字段 {} 重复了", name, field), *span);
+                    }
+                    names.push(field);
+                }
+                // 先登记, 字段的类型里可以用自己
+                self.structs.insert(name.clone(), fields.clone());
+                for (declare_type, _) in fields {
+                    self.check_declare_type(declare_type, *span);
+                }
+            }
+
+            AstNodeType::EnumDeclaration {
+                name,
+                variants,
+                span,
+            } => {
+                if self.enums.contains_key(name) || self.structs.contains_key(name) {
+                    self.error(format!("{} 已经声明过了", name), *span);
+                    return;
+                }
+                let mut names: Vec<&String> = vec![];
+                for (variant, _) in variants {
+                    if names.contains(&variant) {
+                        self.error(format!("{} 的成员 {} 重复了", name, variant), *span);
+                    }
+                    names.push(variant);
+                }
+                // 先登记, 成员的值里可以用自己
+                self.enums.insert(name.clone(), variants.clone());
+                for declare_type in variants.iter().flat_map(|(_, types)| types) {
+                    self.check_declare_type(declare_type, *span);
+                }
+            }
+
+            AstNodeType::MatchStatement { target, arms, span } => {
+                self.check_match(target, arms, *span)
+            }
+
+            AstNodeType::ReturnExp { exp, span } => {
+                let actual = exp.as_ref().map(|exp| self.check_exp(exp));
+                match (self.fn_returns.last().cloned(), actual) {
+                    (None, _) => self.error("return 只能在函数里使用".to_string(), *span),
+                    (Some(None), Some(_)) => self.error("void 函数不能返回值".to_string(), *span),
+                    (Some(Some(expected)), None) => self.error(
+                        format!("返回值应该是 {}, 不能是空的return", expected),
+                        *span,
+                    ),
+                    (Some(Some(expected)), Some(Some(actual))) if actual != expected => self.error(
+                        format!("返回值应该是 {}, 实际是 {}", expected, actual),
+                        *span,
+                    ),
+                    _ => {}
+                }
+            }
+
+            AstNodeType::FnDeclaration {
+                return_type,
+                identifier,
+                argu_list,
+                block,
+                span,
+                ..
+            } => {
+                if let Some(return_type) = return_type {
+                    self.check_declare_type(return_type, *span);
+                }
+                // 先声明, 函数体里可以调用自己
+                self.declare_symbol(
+                    identifier,
+                    Symbol {
+                        declare_type: Some(fn_type(return_type, argu_list)),
+                        inferred: false,
+                        defaults: default_count(argu_list),
+                        hoisted: false,
+                    },
+                );
+                self.check_fn_body(return_type, argu_list, block, *span);
+            }
+
+            _ => {
+                self.check_exp(ast);
+            }
+        }
+    }
+
+    /**
+     * 推导表达式的类型
+     * 返回None表示类型未知(已经报过错), 外层不再重复报错
+     * void函数调用的结果是Void
+     */
+    fn check_exp(&mut self, ast: &AstNodeType) -> Option<DeclareType> {
+        match ast {
+            AstNodeType::IntLiteral(..) => Some(DeclareType::Int),
+            AstNodeType::LongLiteral(..) => Some(DeclareType::Long),
+            AstNodeType::FloatLiteral(..) => Some(DeclareType::Float),
+            AstNodeType::BoolLiteral(..) => Some(DeclareType::Bool),
+            AstNodeType::StringLiteral(..) => Some(DeclareType::String),
+
+            AstNodeType::Identifier { ident, span, .. } => match self.lookup(ident) {
+                Some(symbol) => symbol.declare_type,
+                None => {
+                    self.error(format!("{} 没有申明", ident), *span);
+                    None
+                }
+            },
+
+            AstNodeType::BinaryExp {
+                op,
+                left,
+                right,
+                span,
+            } => {
+                let usage = format!("用于 {}", op);
+                let left = self.check_value(left, &usage);
+                let right = self.check_value(right, &usage);
+                match (left?, right?) {
+                    // 同一种数字类型才能运算, 结果也是这个类型
+                    (left, right) if is_number(&left) && left == right => Some(left),
+                    (DeclareType::String, DeclareType::String) if op == "+" => {
+                        Some(DeclareType::String)
+                    }
+                    (left, right) if is_number(&left) && is_number(&right) => {
+                        self.error(
+                            format!(
+                                "{} {} {} 类型不匹配, 需要先用 to_int, to_long 或 to_float 转换",
+                                left, op, right
+                            ),
+                            *span,
+                        );
+                        None
+                    }
+                    (left, right) => {
+                        self.error(format!("{} {} {} 类型不匹配", left, op, right), *span);
+                        None
+                    }
+                }
+            }
+
+            AstNodeType::UnaryExp { op, exp, span } => {
+                let actual = self.check_value(exp, &format!("用于 {}", op))?;
+                let valid = match op.as_str() {
+                    "!" => actual == DeclareType::Bool,
+                    _ => is_number(&actual),
+                };
+                if !valid {
+                    self.error(format!("{} 不能用于 {}", op, actual), *span);
+                    return None;
+                }
+                Some(actual)
+            }
+
+            AstNodeType::JudgeExp {
+                left,
+                right,
+                judge,
+                span,
+            } => {
+                let usage = format!("用 {} 比较", judge);
+                let left = self.check_value(left, &usage);
+                let right = self.check_value(right, &usage);
+                if let (Some(left), Some(right)) = (left, right) {
+                    let comparable = match (&left, &right) {
+                        (DeclareType::Int, DeclareType::Int)
+                        | (DeclareType::Long, DeclareType::Long)
+                        | (DeclareType::Float, DeclareType::Float)
+                        | (DeclareType::String, DeclareType::String) => true,
+                        (DeclareType::Bool, DeclareType::Bool) => judge == "==" || judge == "!=",
+                        _ => false,
+                    };
+                    if !comparable {
+                        self.error(
+                            format!("{} 和 {} 不能用 {} 比较", left, right, judge),
+                            *span,
+                        );
+                    }
+                }
+                Some(DeclareType::Bool)
+            }
+
+            AstNodeType::LogicExp {
+                op, left, right, ..
+            } => {
+                for exp in [left, right] {
+                    match self.check_value(exp, &format!("用于 {}", op)) {
+                        Some(DeclareType::Bool) | None => {}
+                        Some(actual) => self.error(
+                            format!("{} 两边应该是 bool, 实际是 {}", op, actual),
+                            exp.span(),
+                        ),
+                    }
+                }
+                Some(DeclareType::Bool)
+            }
+
+            AstNodeType::AssignmentStatement {
+                ident,
+                additive,
+                span,
+                ..
+            } => {
+                let actual = self.check_exp(additive);
+                let Some(symbol) = self.lookup(ident) else {
+                    self.error(format!("{} 没有申明", ident), *span);
+                    return None;
+                };
+                let expected = symbol.declare_type?;
+                if let Some(literal) = empty_literal_mismatch(additive, &expected) {
+                    self.error(
+                        format!("{} 的类型是 {}, 不能赋值为{}", ident, expected, literal),
+                        *span,
+                    );
+                } else if let Some(actual) = actual {
+                    if actual != expected {
+                        let message = if symbol.inferred {
+                            format!(
+                                "{} 的类型由初始值推导为 {}, 不能赋值为 {}",
+                                ident, expected, actual
+                            )
+                        } else {
+                            format!("{} 的类型是 {}, 不能赋值为 {}", ident, expected, actual)
+                        };
+                        self.error(message, *span);
+                    }
+                }
+                Some(expected)
+            }
+
+            AstNodeType::FnExpression {
+                return_type,
+                argu_list,
+                block,
+                span,
+            } => {
+                if let Some(return_type) = return_type {
+                    self.check_declare_type(return_type, *span);
+                }
+                self.check_fn_body(return_type, argu_list, block, *span);
+                Some(fn_type(return_type, argu_list))
+            }
+
+            AstNodeType::ArrayLiteral { elements, .. } => {
+                let item = self.check_same_type(elements.iter(), "数组元素")?;
+                Some(DeclareType::Array(Box::new(item)))
+            }
+
+            AstNodeType::MapLiteral { entries, span } => {
+                let key = self.check_same_type(entries.iter().map(|(key, _)| key), "map 的 key ");
+                let value =
+                    self.check_same_type(entries.iter().map(|(_, value)| value), "map 的 value ");
+                let key = key?;
+                if !self.check_key_type(&key, *span) {
+                    return None;
+                }
+                Some(DeclareType::Map {
+                    key: Box::new(key),
+                    value: Box::new(value?),
+                })
+            }
+
+            AstNodeType::IndexExp { target, index, .. } => self.check_index(target, index),
+
+            AstNodeType::StructLiteral { name, fields, span } => {
+                let declared = self.structs.get(name).cloned();
+                if declared.is_none() {
+                    self.error(format!("{} 不是已知的结构体", name), *span);
+                }
+                let mut inited: Vec<&String> = vec![];
+                for (field, value, field_span) in fields {
+                    let actual = self.check_exp(value);
+                    let Some(declared) = &declared else {
+                        continue;
+                    };
+                    if inited.contains(&field) {
+                        self.error(format!("{} 的字段 {} 重复赋值", name, field), *field_span);
+                        continue;
+                    }
+                    inited.push(field);
+                    match (declared.iter().find(|(_, f)| f == field), actual) {
+                        (None, _) => {
+                            self.error(format!("{} 没有字段 {}", name, field), *field_span)
+                        }
+                        (Some((expected, _)), Some(actual)) if actual != *expected => self.error(
+                            format!("{}.{} 应该是 {}, 实际是 {}", name, field, expected, actual),
+                            *field_span,
+                        ),
+                        _ => {}
+                    }
+                }
+                // 不认识的结构体已经报过错, 不再往外传
+                let declared = declared?;
+                for (_, field) in &declared {
+                    if !inited.contains(&field) {
+                        self.error(format!("{} 缺少字段 {}", name, field), *span);
+                    }
+                }
+                Some(DeclareType::Named(name.clone()))
+            }
+
+            AstNodeType::FieldExp {
+                target,
+                field,
+                span,
+            } => self.check_field(target, field, *span),
+
+            AstNodeType::EnumLiteral {
+                name,
+                variant,
+                args,
+                span,
+            } => {
+                let arg_types: Vec<_> = args.iter().map(|arg| self.check_exp(arg)).collect();
+                let Some(variants) = self.enums.get(name) else {
+                    self.error(format!("{} 不是已知的枚举", name), *span);
+                    return None;
+                };
+                let Some((_, expected)) = variants.iter().find(|(v, _)| v == variant).cloned()
+                else {
+                    self.error(format!("{} 没有成员 {}", name, variant), *span);
+                    return None;
+                };
+                if expected.len() != args.len() {
+                    self.error(
+                        format!(
+                            "{}::{} 需要{}个值, 实际传了{}个",
+                            name,
+                            variant,
+                            expected.len(),
+                            args.len()
+                        ),
+                        *span,
+                    );
+                } else {
+                    for (index, (expected, actual)) in expected.iter().zip(arg_types).enumerate() {
+                        match actual {
+                            Some(actual) if actual != *expected => self.error(
+                                format!(
+                                    "{}::{} 的第{}个值应该是 {}, 实际是 {}",
+                                    name,
+                                    variant,
+                                    index + 1,
+                                    expected,
+                                    actual
+                                ),
+                                args[index].span(),
+                            ),
+                            _ => {}
+                        }
+                    }
+                }
+                Some(DeclareType::Named(name.clone()))
+            }
+
+            AstNodeType::FieldAssignment {
+                target,
+                field,
+                additive,
+                span,
+            } => {
+                let expected = self.check_field(target, field, *span);
+                let actual = self.check_exp(additive);
+                if let (Some(expected), Some(actual)) = (&expected, actual) {
+                    if actual != *expected {
+                        self.error(
+                            format!("{} 应该是 {}, 实际是 {}", field, expected, actual),
+                            *span,
+                        );
+                    }
+                }
+                expected
+            }
+
+            AstNodeType::IndexAssignment {
+                target,
+                index,
+                additive,
+                span,
+            } => {
+                let expected = self.check_index(target, index);
+                let actual = self.check_exp(additive);
+                if let (Some(expected), Some(actual)) = (&expected, actual) {
+                    if actual != *expected {
+                        self.error(
+                            format!("下标赋值应该是 {}, 实际是 {}", expected, actual),
+                            *span,
+                        );
+                    }
+                }
+                expected
+            }
+
+            AstNodeType::FnCall {
+                identifier,
+                argu_list,
+                span,
+            } => self.check_call(identifier, argu_list, *span),
+
+            AstNodeType::Statement { .. }
+            | AstNodeType::Declaration { .. }
+            | AstNodeType::VarDeclaration { .. }
+            | AstNodeType::IfStatement { .. }
+            | AstNodeType::Block { .. }
+            | AstNodeType::WhileStatement { .. }
+            | AstNodeType::ForStatement { .. }
+            | AstNodeType::BreakStatement { .. }
+            | AstNodeType::ContinueStatement { .. }
+            | AstNodeType::ReturnExp { .. }
+            | AstNodeType::StructDeclaration { .. }
+            | AstNodeType::EnumDeclaration { .. }
+            | AstNodeType::MatchStatement { .. }
+            | AstNodeType::FnDeclaration { .. } => {
+                self.check(ast);
+                None
+            }
+        }
+    }
+
+    /**
+     * 字面量里的表达式类型要一致
+     * 为空或者有类型未知的表达式时推导不出来
+     */
+    fn check_same_type<'a>(
+        &mut self,
+        exps: impl Iterator<Item = &'a AstNodeType>,
+        what: &str,
+    ) -> Option<DeclareType> {
+        let mut same_type: Option<DeclareType> = None;
+        let mut known = true;
+        for exp in exps {
+            match (
+                self.check_value(exp, &format!("作为{}", what.trim())),
+                &same_type,
+            ) {
+                (None, _) => known = false,
+                (Some(actual), Some(expected)) if actual != *expected => self.error(
+                    format!("{}应该都是 {}, 实际是 {}", what, expected, actual),
+                    exp.span(),
+                ),
+                (Some(_), Some(_)) => {}
+                (Some(actual), None) => same_type = Some(actual),
+            }
+        }
+        if !known {
+            return None;
+        }
+        same_type
+    }
+
+    // map的key只能是int或者string
+    fn check_key_type(&mut self, key: &DeclareType, span: Span) -> bool {
+        match key {
+            DeclareType::Int | DeclareType::String => true,
+            other => {
+                self.error(
+                    format!("map 的 key 只能是 int 或 string, 实际是 {}", other),
+                    span,
+                );
+                false
+            }
+        }
+    }
+
+    /**
+     * 检查声明里写的类型, 包括嵌套在数组和函数类型里的
+     */
+    fn check_declare_type(&mut self, declare_type: &DeclareType, span: Span) {
+        match declare_type {
+            DeclareType::Array(item) => self.check_declare_type(item, span),
+            DeclareType::Map { key, value } => {
+                self.check_key_type(key, span);
+                self.check_declare_type(value, span);
+            }
+            DeclareType::FnType {
+                return_type,
+                argu_list,
+            } => {
+                if let Some(return_type) = return_type {
+                    self.check_declare_type(return_type, span);
+                }
+                for argu in argu_list.iter() {
+                    self.check_declare_type(argu, span);
+                }
+            }
+            DeclareType::Named(name) => {
+                if !self.structs.contains_key(name) && !self.enums.contains_key(name) {
+                    self.error(format!("{} 不是已知的类型", name), span);
+                }
+            }
+            DeclareType::Int
+            | DeclareType::Long
+            | DeclareType::Float
+            | DeclareType::String
+            | DeclareType::Bool
+            | DeclareType::Void => {}
+        }
+    }
+
+    /**
+     * 需要值的地方, 比如运算, 比较和初始值, 不能用void函数的结果
+     */
+    fn check_value(&mut self, ast: &AstNodeType, usage: &str) -> Option<DeclareType> {
+        match self.check_exp(ast) {
+            Some(DeclareType::Void) => {
+                self.error(format!("void 没有值, 不能{}", usage), ast.span());
+                None
+            }
+            declare_type => declare_type,
+        }
+    }
+
+    /**
+     * 字段访问, 返回字段的类型
+     */
+    fn check_field(
+        &mut self,
+        target: &AstNodeType,
+        field: &str,
+        span: Span,
+    ) -> Option<DeclareType> {
+        let name = match self.check_exp(target)? {
+            DeclareType::Named(name) => name,
+            other => {
+                self.error(format!("{} 没有字段 {}", other, field), span);
+                return None;
+            }
+        };
+        let Some(declared) = self.structs.get(&name) else {
+            // 枚举没有字段, 没声明的类型已经报过错
+            if self.enums.contains_key(&name) {
+                self.error(format!("{} 没有字段 {}", name, field), span);
+            }
+            return None;
+        };
+        match declared.iter().find(|(_, f)| f == field) {
+            Some((declare_type, _)) => Some(declare_type.clone()),
+            None => {
+                self.error(format!("{} 没有字段 {}", name, field), span);
+                None
+            }
+        }
+    }
+
+    /**
+     * match的值必须是枚举, 分支要覆盖所有成员或者有 _
+     * 每个分支一个作用域, 绑定的变量只在分支里可见
+     */
+    fn check_match(&mut self, target: &AstNodeType, arms: &[MatchArm], span: Span) {
+        let declared = match self.check_exp(target) {
+            Some(DeclareType::Named(name)) if self.enums.contains_key(&name) => {
+                let variants = self.enums[&name].clone();
+                Some((name, variants))
+            }
+            Some(other) => {
+                self.error(
+                    format!("match 的值应该是枚举, 实际是 {}", other),
+                    target.span(),
+                );
+                None
+            }
+            None => None,
+        };
+        let mut matched: Vec<&String> = vec![];
+        let mut has_wildcard = false;
+        for arm in arms {
+            // 类型未知时绑定的变量也是未知
+            let mut payload = vec![None; arm.bindings.len()];
+            match (&arm.variant, &declared) {
+                (None, _) => has_wildcard = true,
+                (Some(variant), Some((name, variants))) => {
+                    if matched.contains(&variant) {
+                        self.error(format!("{} 已经匹配过了", variant), arm.span);
+                    }
+                    matched.push(variant);
+                    match variants.iter().find(|(v, _)| v == variant) {
+                        None => self.error(format!("{} 没有成员 {}", name, variant), arm.span),
+                        Some((_, types)) if types.len() != arm.bindings.len() => self.error(
+                            format!(
+                                "{}::{} 有{}个值, 绑定了{}个",
+                                name,
+                                variant,
+                                types.len(),
+                                arm.bindings.len()
+                            ),
+                            arm.span,
+                        ),
+                        Some((_, types)) => payload = types.iter().cloned().map(Some).collect(),
+                    }
+                }
+                (Some(_), None) => {}
+            }
+            self.scopes.push(HashMap::new());
+            for (binding, declare_type) in arm.bindings.iter().zip(payload) {
+                self.declare_symbol(
+                    binding,
+                    Symbol {
+                        declare_type,
+                        inferred: false,
+                        defaults: 0,
+                        hoisted: false,
+                    },
+                );
+            }
+            self.check(&arm.block);
+            self.scopes.pop();
+        }
+        if let (Some((name, variants)), false) = (&declared, has_wildcard) {
+            let mut missing: Vec<&str> = vec![];
+            for (variant, _) in variants {
+                if !matched.contains(&variant) && !missing.contains(&variant.as_str()) {
+                    missing.push(variant);
+                }
+            }
+            if !missing.is_empty() {
+                self.error(
+                    format!("match 没有覆盖 {} 的 {}", name, missing.join(", ")),
+                    span,
+                );
+            }
+        }
+    }
+
+    /**
+     * 下标访问, 返回元素的类型
+     * 数组的下标是int, map的下标是key
+     */
+    fn check_index(&mut self, target: &AstNodeType, index: &AstNodeType) -> Option<DeclareType> {
+        let target_type = self.check_exp(target);
+        let index_type = self.check_exp(index);
+        let (expected, item) = match target_type? {
+            DeclareType::Array(item) => (DeclareType::Int, *item),
+            DeclareType::Map { key, value } => (*key, *value),
+            other => {
+                self.error(format!("{} 不能用下标访问", other), target.span());
+                return None;
+            }
+        };
+        match index_type {
+            Some(actual) if actual != expected => self.error(
+                format!("下标应该是 {}, 实际是 {}", expected, actual),
+                index.span(),
+            ),
+            _ => {}
+        }
+        Some(item)
+    }
+
+    /**
+     * 预置函数, 没有被同名变量覆盖时才生效
+     */
+    fn check_builtin(
+        &mut self,
+        name: &str,
+        argu_list: &[AstNodeType],
+        span: Span,
+    ) -> Option<DeclareType> {
+        let mut actuals = vec![];
+        for argu in argu_list {
+            actuals.push(self.check_exp(argu));
+        }
+        let arity = match name {
+            "len" | "keys" | "to_int" | "to_long" | "to_float" => 1,
+            _ => 2,
+        };
+        if actuals.len() != arity {
+            self.error(
+                format!("{} 需要{}个参数, 实际传了{}个", name, arity, actuals.len()),
+                span,
+            );
+            return None;
+        }
+        match (name, actuals[0].clone()) {
+            // 数字类型之间的转换
+            ("to_int" | "to_long" | "to_float", actual) => {
+                match actual {
+                    Some(actual) if !is_number(&actual) => self.error(
+                        format!(
+                            "{} 的参数应该是 int, long 或者 float, 实际是 {}",
+                            name, actual
+                        ),
+                        argu_list[0].span(),
+                    ),
+                    _ => {}
+                }
+                match name {
+                    "to_int" => Some(DeclareType::Int),
+                    "to_long" => Some(DeclareType::Long),
+                    _ => Some(DeclareType::Float),
+                }
+            }
+            ("len", Some(DeclareType::Array(_)))
+            | ("len", Some(DeclareType::String))
+            | ("len", Some(DeclareType::Map { .. }))
+            | ("len", None) => Some(DeclareType::Int),
+            ("len", Some(actual)) => {
+                self.error(
+                    format!("len 的参数应该是数组, map 或者 string, 实际是 {}", actual),
+                    argu_list[0].span(),
+                );
+                Some(DeclareType::Int)
+            }
+            ("keys", Some(DeclareType::Map { key, .. })) => Some(DeclareType::Array(key)),
+            // contains(m, k), remove(m, k), 返回key是否存在
+            (_, Some(DeclareType::Map { key, .. })) => {
+                match &actuals[1] {
+                    Some(actual) if actual != key.as_ref() => self.error(
+                        format!("{} 的 key 应该是 {}, 实际是 {}", name, key, actual),
+                        argu_list[1].span(),
+                    ),
+                    _ => {}
+                }
+                Some(DeclareType::Bool)
+            }
+            ("keys", None) => None,
+            (_, None) => Some(DeclareType::Bool),
+            (_, Some(actual)) => {
+                self.error(
+                    format!("{} 的第1个参数应该是 map, 实际是 {}", name, actual),
+                    argu_list[0].span(),
+                );
+                None
+            }
+        }
+    }
+
+    // if/while/for的条件必须是bool
+    fn check_cond(&mut self, judge_stat: &AstNodeType, keyword: &str) {
+        match self.check_exp(judge_stat) {
+            Some(DeclareType::Bool) | None => {}
+            Some(actual) => self.error(
+                format!("{} 的条件应该是 bool, 实际是 {}", keyword, actual),
+                judge_stat.span(),
+            ),
+        }
+    }
+
+    /**
+     * 函数体在新的作用域里检查, 形参先声明
+     * 有返回类型的函数, 每条路径最后都要return
+     */
+    fn check_fn_body(
+        &mut self,
+        return_type: &Option<Box<DeclareType>>,
+        argu_list: &[AstNodeType],
+        block: &AstNodeType,
+        span: Span,
+    ) {
+        if return_type.is_some() && !always_returns(block) {
+            self.error("缺少返回值, 不是每条路径都有 return".to_string(), span);
+        }
+        self.scopes.push(HashMap::new());
+        // 默认值在调用时按顺序计算, 可以用前面的参数
+        let mut has_default = false;
+        for argu in argu_list {
+            if let AstNodeType::Declaration {
+                declare_type,
+                identifier,
+                additive: None,
+                span,
+                ..
+            } = argu
+            {
+                // 没有默认值的参数由调用方传值
+                if has_default {
+                    self.error(
+                        format!("有默认值的参数后面, {} 也要有默认值", identifier),
+                        *span,
+                    );
+                }
+                self.check_declare_type(declare_type, *span);
+                self.declare(identifier, declare_type.clone());
+            } else {
+                // 有默认值的参数和普通声明一样检查初始值的类型
+                has_default = true;
+                self.check(argu);
+            }
+        }
+        self.fn_returns.push(return_type.as_deref().cloned());
+        self.fn_depths.push(self.scopes.len());
+        // 函数体里的break/continue不能跳出外面的循环
+        let loops = std::mem::take(&mut self.loops);
+        self.check(block);
+        self.loops = loops;
+        self.fn_depths.pop();
+        self.fn_returns.pop();
+        self.scopes.pop();
+    }
+
+    /**
+     * 检查调用的入参个数和类型, 返回函数的返回类型
+     */
+    fn check_call(
+        &mut self,
+        identifier: &AstNodeType,
+        argu_list: &[AstNodeType],
+        span: Span,
+    ) -> Option<DeclareType> {
+        if let AstNodeType::Identifier { ident, .. } = identifier {
+            if is_builtin(ident) && self.lookup(ident).is_none() {
+                return self.check_builtin(ident, argu_list, span);
+            }
+        }
+        let callee = self.check_exp(identifier);
+        let mut actuals = vec![];
+        for argu in argu_list {
+            actuals.push(self.check_exp(argu));
+        }
+        let name = match identifier {
+            AstNodeType::Identifier { ident, .. } => ident.clone(),
+            _ => String::from("函数"),
+        };
+
+        match callee? {
+            DeclareType::FnType {
+                return_type,
+                argu_list: params,
+            } => {
+                // 直接调用声明的函数时, 末尾有默认值的参数可以不传
+                let defaults = match identifier {
+                    AstNodeType::Identifier { ident, .. } => {
+                        self.lookup(ident).map_or(0, |symbol| symbol.defaults)
+                    }
+                    _ => 0,
+                };
+                let required = params.len() - defaults;
+                if actuals.len() < required || actuals.len() > params.len() {
+                    let expected = if defaults > 0 {
+                        format!("{}到{}", required, params.len())
+                    } else {
+                        params.len().to_string()
+                    };
+                    self.error(
+                        format!(
+                            "{} 需要{}个参数, 实际传了{}个{}",
+                            name,
+                            expected,
+                            actuals.len(),
+                            self.inferred_note(identifier)
+                        ),
+                        span,
+                    );
+                }
+                for (index, (param, actual)) in params.iter().zip(actuals).enumerate() {
+                    match actual {
+                        Some(actual) if actual != *param => self.error(
+                            format!(
+                                "{} 的第{}个参数应该是 {}, 实际是 {}{}",
+                                name,
+                                index + 1,
+                                param,
+                                actual,
+                                self.inferred_note(&argu_list[index])
+                            ),
+                            argu_list[index].span(),
+                        ),
+                        _ => {}
+                    }
+                }
+                Some(return_type.map_or(DeclareType::Void, |return_type| *return_type))
+            }
+            other => {
+                let note = self.inferred_note(identifier);
+                self.error(
+                    format!("{} 的类型是 {}, 不能调用{}", name, other, note),
+                    span,
+                );
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,555 @@
+use super::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pest::parse_ast::parse_code;
+
+    // 返回所有类型错误的信息
+    fn check_code(code: &str) -> Vec<String> {
+        let asts = parse_code(code.to_string()).unwrap();
+        match type_check(&asts) {
+            Ok(_) => vec![],
+            Err(SimpleError::Type(errors)) => errors.into_iter().map(|e| e.message).collect(),
+            Err(e) => panic!("不是类型错误: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_well_typed() {
+        let code = r#"int c = 2;
+        string s = "a" + "b";
+        fn int () a () {
+          int b = c;
+          int d () {
+            return b;
+          }
+          return d;
+        }
+        fn int () f = a();
+        int fact (int n) {
+          if (n <= 1) { return 1; }
+          return n * fact(n - 1);
+        }
+        for (int i = 0; i < 3 && s != ""; i = i + 1) {
+          c = c + f() + fact(i);
+        }"#;
+        assert_eq!(check_code(code), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_declaration_and_assignment() {
+        let code = r#"int a = "x";
+        string s = 1 + 2;
+        a = "y";
+        b = 1;
+        a = 3;"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "a 声明为 int, 初始值是 string",
+                "s 声明为 string, 初始值是 int",
+                "a 的类型是 int, 不能赋值为 string",
+                "b 没有申明",
+            ]
+        );
+    }
+
+    #[test]
+    // 声明语句要有初始值, 只有参数可以不写默认值
+    fn test_declaration_without_initializer() {
+        let code = r#"int a;
+        a = 1;
+        for (int i; i < 3; i = i + 1) { }
+        int f (int x, int y = 1) { return x + y; }"#;
+        assert_eq!(
+            check_code(code),
+            vec!["a 声明时没有初始值", "i 声明时没有初始值"]
+        );
+    }
+
+    #[test]
+    fn test_bool_declaration() {
+        let code = r#"bool ok = 1 < 2;
+        bool bad = 1 + 2;
+        int n = ok;
+        fn bool (bool) not = fn bool (bool b) { return !b; };
+        ok = not(ok);"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "bad 声明为 bool, 初始值是 int",
+                "n 声明为 int, 初始值是 bool"
+            ]
+        );
+    }
+
+    #[test]
+    // var/let的类型由初始值推导, 包括函数返回的闭包
+    fn test_infer() {
+        let code = r#"fn int (int) make () {
+          return fn int (int x) { return x + 1; };
+        }
+        var n = 1;
+        let s = "a";
+        var cb = make();
+        var m = make;
+        n = cb(n);
+        fn int (int) f = m();
+        for (var i = 0; i < 3; i = i + 1) {
+          s = s + "b";
+        }"#;
+        assert_eq!(check_code(code), Vec::<String>::new());
+    }
+
+    #[test]
+    // 错误信息里带上推导出的类型
+    fn test_infer_mismatch() {
+        let code = r#"fn int (int) make () {
+          return fn int (int x) { return x + 1; };
+        }
+        var n = 1;
+        var s = "a";
+        var cb = make();
+        n = "x";
+        cb(s);
+        cb(1, 2);
+        s(1);"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "n 的类型由初始值推导为 int, 不能赋值为 string",
+                "cb 的第1个参数应该是 int, 实际是 string, s 的类型由初始值推导为 string",
+                "cb 需要1个参数, 实际传了2个, cb 的类型由初始值推导为 fn int (int)",
+                "s 的类型是 string, 不能调用, s 的类型由初始值推导为 string",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_array() {
+        let code = r#"int[] a = [1, 2];
+        int[] empty = [];
+        string[] b = [1, 2];
+        var c = [1, "x"];
+        var d = [];
+        a[0] = "x";
+        a["0"] = 1;
+        int n = a[0] + len(a);
+        n[0];
+        len(n);"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "b 声明为 string[], 初始值是 int[]",
+                "数组元素应该都是 int, 实际是 string",
+                "无法从空数组推导 d 的类型",
+                "下标赋值应该是 int, 实际是 string",
+                "下标应该是 int, 实际是 string",
+                "int 不能用下标访问",
+                "len 的参数应该是数组, map 或者 string, 实际是 int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map() {
+        let code = r#"map<string, int> m = {"a": 1, "b": 2};
+        map<bool, int> bad_key = {};
+        var mixed = {"a": 1, "b": "x"};
+        var empty = {};
+        m[1] = 2;
+        m["c"] = "x";
+        string s = m["a"];
+        contains(m, 1);
+        remove(1, 1);
+        int[] k = keys(m);"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "map 的 key 只能是 int 或 string, 实际是 bool",
+                "map 的 value 应该都是 int, 实际是 string",
+                "无法从空 map 推导 empty 的类型",
+                "下标应该是 string, 实际是 int",
+                "下标赋值应该是 int, 实际是 string",
+                "s 声明为 string, 初始值是 int",
+                "contains 的 key 应该是 string, 实际是 int",
+                "remove 的第1个参数应该是 map, 实际是 int",
+                "k 声明为 int[], 初始值是 string[]",
+            ]
+        );
+    }
+
+    #[test]
+    // 空数组只能给数组, 空map只能给map
+    fn test_empty_literal() {
+        let code = r#"string s = [];
+        int x = {};
+        int[] a = {};
+        map<string, int> m = [];
+        int[] ok = [];
+        map<string, int> ok2 = {};
+        s = [];
+        ok = {};
+        ok2 = {};"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "s 声明为 string, 初始值是空数组",
+                "x 声明为 int, 初始值是空 map",
+                "a 声明为 int[], 初始值是空 map",
+                "m 声明为 map<string, int>, 初始值是空数组",
+                "s 的类型是 string, 不能赋值为空数组",
+                "ok 的类型是 int[], 不能赋值为空 map",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_call_arguments() {
+        let code = r#"int add (int a, string b) {
+          return a;
+        }
+        add("1", 2);
+        add(1);
+        int x = 1;
+        x(1);"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "add 的第1个参数应该是 int, 实际是 string",
+                "add 的第2个参数应该是 string, 实际是 int",
+                "add 需要2个参数, 实际传了1个",
+                "x 的类型是 int, 不能调用",
+            ]
+        );
+    }
+
+    #[test]
+    // 不同的数字类型不能混用, 要先显式转换
+    fn test_number_types() {
+        let code = r#"int a = 1;
+        long b = 2L;
+        float c = 1.5;
+        float d = 1;
+        long e = a + b;
+        bool f = c > a;
+        float g = -c * to_float(a) + to_float(b);
+        long h = to_long(c) % b;
+        int i = to_int("1");
+        to_float(1, 2);
+        string s = -"x";"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "d 声明为 float, 初始值是 int",
+                "int + long 类型不匹配, 需要先用 to_int, to_long 或 to_float 转换",
+                "float 和 int 不能用 > 比较",
+                "to_int 的参数应该是 int, long 或者 float, 实际是 string",
+                "to_float 需要1个参数, 实际传了2个",
+                "- 不能用于 string",
+            ]
+        );
+    }
+
+    #[test]
+    // void函数的结果不能参与运算, 比较, 也不能当初始值
+    fn test_void_value() {
+        let code = r#"void log (int n) { }
+        int a = log(1) + 1;
+        bool b = log(1) == log(2);
+        int c = log(1);
+        var d = log(1);
+        var e = [log(1)];
+        bool f = !log(1) && true;
+        log(log(1));
+        log(2);"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "void 没有值, 不能用于 +",
+                "void 没有值, 不能用 == 比较",
+                "void 没有值, 不能用 == 比较",
+                "void 没有值, 不能作为 c 的初始值",
+                "void 没有值, 不能作为 d 的初始值",
+                "void 没有值, 不能作为数组元素",
+                "void 没有值, 不能用于 !",
+                "log 的第1个参数应该是 int, 实际是 void",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_params() {
+        let code = r#"int add (int a, int b = 10, int c = a + b) {
+          return a + b + c;
+        }
+        add(1);
+        add(1, 2, 3);
+        add();
+        add(1, 2, 3, 4);
+        add(1, "2");
+        void bad (int a = 1, string b, int c = "x") { }
+        fn int (int, int, int) f = add;
+        f(1);"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "add 需要1到3个参数, 实际传了0个",
+                "add 需要1到3个参数, 实际传了4个",
+                "add 的第2个参数应该是 int, 实际是 string",
+                "有默认值的参数后面, b 也要有默认值",
+                "c 声明为 int, 初始值是 string",
+                "f 需要3个参数, 实际传了1个",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_return_type() {
+        let code = r#"int a () {
+          if (true) {
+            return "x";
+          }
+          return;
+        }
+        void b () {
+          return 1;
+        }
+        fn int () c () {
+          return fn string () { return "s"; };
+        }
+        return 1;"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "返回值应该是 int, 实际是 string",
+                "返回值应该是 int, 不能是空的return",
+                "void 函数不能返回值",
+                "返回值应该是 fn int (), 实际是 fn string ()",
+                "return 只能在函数里使用",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_struct() {
+        let code = r#"struct Point {
+          int x;
+          int y;
+          int x;
+        }
+        struct Node {
+          int value;
+          Node[] children;
+          Shape shape;
+        }
+        Point a = Point { x: 1, y: "2" };
+        Point b = Point { x: 1, z: 2 };
+        Point c = Circle { r: 1 };
+        string s = a.x;
+        a.z = 1;
+        a.y = "3";
+        int n = 1;
+        n.x;"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "Point 的字段 x 重复了",
+                "Shape 不是已知的类型",
+                "Point.y 应该是 int, 实际是 string",
+                "Point 没有字段 z",
+                "Point 缺少字段 y",
+                "Circle 不是已知的结构体",
+                "s 声明为 string, 初始值是 int",
+                "Point 没有字段 z",
+                "y 应该是 int, 实际是 string",
+                "int 没有字段 x",
+            ]
+        );
+    }
+
+    #[test]
+    // 有返回类型的函数, 每条路径最后都要return
+    fn test_missing_return() {
+        let code = r#"int a () { }
+        int b (bool c) {
+          if (c) { return 1; }
+        }
+        int d (bool c) {
+          while (c) { return 1; }
+        }
+        var e = fn int () { 1; };
+        int ok (bool c) {
+          if (c) { return 1; } else { return 2; }
+        }
+        enum Color { Red, Green }
+        int ok2 (Color c) {
+          match (c) {
+            Red => { return 1; }
+            Green => { { return 2; } }
+          }
+        }
+        void ok3 () { }"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "缺少返回值, 不是每条路径都有 return",
+                "缺少返回值, 不是每条路径都有 return",
+                "缺少返回值, 不是每条路径都有 return",
+                "缺少返回值, 不是每条路径都有 return",
+            ]
+        );
+    }
+
+    #[test]
+    // 重复声明的结构体报在第二次声明的位置, 不会覆盖第一次的字段
+    fn test_duplicate_struct() {
+        let code =
+            String::from("struct Foo { int x; }\nstruct Foo { string y; }\nFoo a = Foo { x: 1 };");
+        let asts = parse_code(code.clone()).unwrap();
+        match type_check(&asts) {
+            Err(SimpleError::Type(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].message, "Foo 已经声明过了");
+                assert_eq!(errors[0].span.line_col(&code), (2, 1));
+            }
+            _ => panic!("应该有类型错误"),
+        }
+    }
+
+    #[test]
+    fn test_enum() {
+        let code = r#"enum Shape {
+          Circle(int),
+          Rect(int, int),
+          Empty,
+          Empty
+        }
+        struct Shape { int x; }
+        Shape a = Shape::Circle("1");
+        Shape b = Shape::Rect(1);
+        Shape c = Shape::Tri(1);
+        var d = Color::Red;
+        match (a) {
+          Circle(r) => { string s = r; }
+          Rect(w) => { }
+          Circle(r) => { }
+        }
+        match (a) {
+          Tri => { }
+          _ => { r; }
+        }
+        match (1) {
+          _ => { }
+        }
+        a.x;"#;
+        assert_eq!(
+            check_code(code),
+            vec![
+                "Shape 的成员 Empty 重复了",
+                "Shape 已经声明过了",
+                "Shape::Circle 的第1个值应该是 int, 实际是 string",
+                "Shape::Rect 需要2个值, 实际传了1个",
+                "Shape 没有成员 Tri",
+                "Color 不是已知的枚举",
+                "s 声明为 string, 初始值是 int",
+                "Shape::Rect 有2个值, 绑定了1个",
+                "Circle 已经匹配过了",
+                "match 没有覆盖 Shape 的 Empty",
+                "Shape 没有成员 Tri",
+                "r 没有申明",
+                "match 的值应该是枚举, 实际是 int",
+                "Shape 没有字段 x",
+            ]
+        );
+    }
+
+    #[test]
+    // 重复声明的枚举报在第二次声明的位置, 不会覆盖第一次的成员
+    fn test_duplicate_enum() {
+        let code =
+            String::from("enum Color { Red, Green }\nenum Color { Blue }\nColor c = Color::Red;");
+        let asts = parse_code(code.clone()).unwrap();
+        match type_check(&asts) {
+            Err(SimpleError::Type(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].message, "Color 已经声明过了");
+                assert_eq!(errors[0].span.line_col(&code), (2, 1));
+            }
+            _ => panic!("应该有类型错误"),
+        }
+    }
+
+    #[test]
+    // 函数体里可以调用后面声明的函数, 函数体外面不行
+    fn test_mutual_recursion() {
+        let code = r#"bool even (int n) {
+          if (n == 0) { return true; }
+          return odd(n - 1);
+        }
+        later();
+        bool odd (int n) {
+          if (n == 0) { return false; }
+          return even(n - 1) + 1;
+        }
+        void later () {
+          {
+            inner();
+            void inner () { }
+          }
+        }"#;
+        assert_eq!(
+            check_code(code),
+            vec!["later 没有申明", "bool + int 类型不匹配", "inner 没有申明"]
+        );
+    }
+
+    #[test]
+    // 块里声明的变量出了块就不能用, 里层的声明覆盖外层
+    fn test_nested_scope() {
+        let code = r#"int a = 1;
+        {
+          string a = "s";
+          int b = 2;
+          a = "t";
+        }
+        a = 2;
+        b = 3;"#;
+        assert_eq!(check_code(code), vec!["b 没有申明"]);
+    }
+
+    #[test]
+    // 错误带上出错节点的位置
+    fn test_error_span() {
+        let code = String::from("int a = 1;\nif (a) {\n  a = 2;\n}");
+        let asts = parse_code(code.clone()).unwrap();
+        match type_check(&asts) {
+            Err(SimpleError::Type(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].message, "if 的条件应该是 bool, 实际是 int");
+                assert_eq!(&code[errors[0].span.start..errors[0].span.end], "a");
+                assert_eq!(errors[0].span.line_col(&code), (2, 5));
+            }
+            _ => panic!("应该有类型错误"),
+        }
+    }
+
+    #[test]
+    // break/continue只能写在当前函数的循环里
+    fn test_break_outside_loop() {
+        let code =
+            String::from("while (true) {\n  void a () {\n    break;\n  }\n  break;\n}\ncontinue;");
+        let asts = parse_code(code.clone()).unwrap();
+        match type_check(&asts) {
+            Err(SimpleError::Type(errors)) => {
+                assert_eq!(errors.len(), 2);
+                assert_eq!(errors[0].message, "break 只能在循环里使用");
+                assert_eq!(errors[0].span.line_col(&code), (3, 5));
+                assert_eq!(errors[1].message, "continue 只能在循环里使用");
+                assert_eq!(errors[1].span.line_col(&code), (7, 1));
+            }
+            _ => panic!("应该有类型错误"),
+        }
+    }
+}
@@ -1,6 +1,214 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::pest::frame::ValType;
+use crate::pest::parse_ast::Span;
+
 #[derive(Debug)]
 pub enum SimpleError {
   Lex(String),
   Ast(String),
   Calc(String),
-}
\ No newline at end of file
+  // 语法错误
+  Parse(Diagnostic),
+  // 运行时错误, span是出错节点的位置, 用Span::line_col换算成行列, trace是出错时的调用栈
+  Runtime { message: String, span: Span, trace: Vec<TraceFrame> },
+  // 类型错误, 执行前的类型检查收集到的所有错误
+  Type(Vec<TypeError>),
+  // 控制流, 借用错误通道向上传递, break/continue由循环捕获, return由函数调用捕获
+  Break,
+  Continue,
+  Return(Rc<ValType>),
+  // 调用层数超过上限, 一般是无限递归, max_depth是上限
+  StackOverflow { max_depth: usize, trace: Vec<TraceFrame> },
+}
+
+/**
+ * 调用栈的一层, 错误经过函数调用往外传的时候从里往外依次加上
+ * name是被调用的函数名, span是调用的位置, depth是被调用函数的层数, 顶层是0
+ */
+#[derive(Debug, Clone)]
+pub struct TraceFrame {
+  pub name: String,
+  pub span: Span,
+  pub depth: usize,
+}
+
+/**
+ * 语法错误的诊断信息, 行列从1开始
+ */
+#[derive(Debug)]
+pub struct Diagnostic {
+  pub line: usize,
+  pub column: usize,
+  // 这个位置期望出现的规则, 比如 表达式, 标识符, ;
+  pub expected: Vec<String>,
+  // 语法上没问题但是不合法时的说明, 比如字面量超出范围
+  pub message: Option<String>,
+  // 出错的那一行源码
+  pub source_line: String,
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "语法错误 {}:{}", self.line, self.column)?;
+    if let Some(message) = &self.message {
+      write!(f, ", {}", message)?;
+    }
+    if !self.expected.is_empty() {
+      write!(f, ", 期望: {}", self.expected.join(", "))?;
+    }
+    // 源码行和指向出错列的 ^
+    let gutter = " ".repeat(self.line.to_string().len());
+    writeln!(f)?;
+    writeln!(f, "{} |", gutter)?;
+    writeln!(f, "{} | {}", self.line, self.source_line)?;
+    write!(f, "{} | {}^", gutter, " ".repeat(self.column.saturating_sub(1)))
+  }
+}
+
+/**
+ * 一条类型错误, span是出错节点的位置
+ */
+#[derive(Debug)]
+pub struct TypeError {
+  pub message: String,
+  pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "类型错误: {} (位置 {}..{})", self.message, self.span.start, self.span.end)
+  }
+}
+
+impl SimpleError {
+  /**
+   * 给运行时错误加上位置, 已经有位置的保留最里层的
+   */
+  pub fn with_span(self, span: Span) -> SimpleError {
+    match self {
+      SimpleError::Calc(message) => SimpleError::Runtime { message, span, trace: vec![] },
+      e => e,
+    }
+  }
+
+  /**
+   * 错误从被调用的函数里出来, 记下这一层调用
+   * 还没有位置的错误(比如缺少返回值)用调用的位置, 这一层也要记下
+   */
+  pub fn push_frame(self, frame: TraceFrame) -> SimpleError {
+    match self {
+      SimpleError::Calc(message) => SimpleError::Runtime { message, span: frame.span, trace: vec![frame] },
+      SimpleError::Runtime { message, span, mut trace } => {
+        trace.push(frame);
+        SimpleError::Runtime { message, span, trace }
+      }
+      SimpleError::StackOverflow { max_depth, mut trace } => {
+        trace.push(frame);
+        SimpleError::StackOverflow { max_depth, trace }
+      }
+      e => e,
+    }
+  }
+
+  /**
+   * 带源码的错误信息, 位置换算成行列
+   */
+  pub fn render(&self, source: &str) -> String {
+    let position = |span: &Span| {
+      let (line, column) = span.line_col(source);
+      format!("第{}行第{}列", line, column)
+    };
+    match self {
+      SimpleError::Runtime { message, span, trace } => {
+        format!("{}运行时错误: {} ({})", traceback(trace, Some(span), &position), message, position(span))
+      }
+      SimpleError::StackOverflow { max_depth, trace } => {
+        format!("{}{}", traceback(trace, None, &position), stack_overflow(*max_depth))
+      }
+      SimpleError::Type(errors) => {
+        let lines: Vec<String> = errors
+          .iter()
+          .map(|e| format!("类型错误: {} ({})", e.message, position(&e.span)))
+          .collect();
+        lines.join("\n")
+      }
+      e => e.to_string(),
+    }
+  }
+}
+
+// 调用栈里连续重复的层最多显示几层
+const REPEAT_LIMIT: usize = 3;
+
+fn omitted(repeated: usize) -> String {
+  if repeated < REPEAT_LIMIT {
+    return String::new();
+  }
+  format!("  ... 上一行又重复了 {} 次\n", repeated + 1 - REPEAT_LIMIT)
+}
+
+fn stack_overflow(max_depth: usize) -> String {
+  format!("调用层数超过上限 {}, 可能是无限递归", max_depth)
+}
+
+/**
+ * 调用栈, 最外层在前, 和常见的traceback一样
+ * 每一层写的是这一层执行到的位置: 外层是调用下一层的位置, 最里层是出错的位置
+ */
+fn traceback(trace: &[TraceFrame], error_span: Option<&Span>, position: &dyn Fn(&Span) -> String) -> String {
+  if trace.is_empty() {
+    return String::new();
+  }
+  let mut out = String::from("调用栈 (最近的调用在最后):\n");
+  // trace是从里往外加的, 反过来就是从外往里
+  let frames: Vec<&TraceFrame> = trace.iter().rev().collect();
+  // 递归时同一个位置会连续重复很多层, 只留前REPEAT_LIMIT层
+  let mut last: Option<(&str, &Span)> = None;
+  let mut repeated = 0;
+  for (i, frame) in frames.iter().enumerate() {
+    let caller = if i == 0 { "<顶层>" } else { frames[i - 1].name.as_str() };
+    if last == Some((caller, &frame.span)) {
+      repeated += 1;
+    } else {
+      out.push_str(&omitted(repeated));
+      repeated = 0;
+      last = Some((caller, &frame.span));
+    }
+    if repeated < REPEAT_LIMIT {
+      out.push_str(&format!("  [{}] {}, {}\n", frame.depth - 1, caller, position(&frame.span)));
+    }
+  }
+  out.push_str(&omitted(repeated));
+  let innermost = frames[frames.len() - 1];
+  match error_span {
+    Some(span) => out.push_str(&format!("  [{}] {}, {}\n", innermost.depth, innermost.name, position(span))),
+    None => out.push_str(&format!("  [{}] {}\n", innermost.depth, innermost.name)),
+  }
+  out
+}
+
+impl fmt::Display for SimpleError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SimpleError::Lex(msg) | SimpleError::Ast(msg) | SimpleError::Calc(msg) => write!(f, "{}", msg),
+      SimpleError::Parse(diagnostic) => write!(f, "{}", diagnostic),
+      SimpleError::Runtime { message, span, trace } => {
+        let position = |span: &Span| format!("位置 {}..{}", span.start, span.end);
+        write!(f, "{}{} ({})", traceback(trace, Some(span), &position), message, position(span))
+      }
+      SimpleError::Type(errors) => {
+        let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+      }
+      SimpleError::Break => write!(f, "break 只能在循环里使用"),
+      SimpleError::Continue => write!(f, "continue 只能在循环里使用"),
+      SimpleError::Return(_) => write!(f, "return 只能在函数里使用"),
+      SimpleError::StackOverflow { max_depth, trace } => {
+        let position = |span: &Span| format!("位置 {}..{}", span.start, span.end);
+        write!(f, "{}{}", traceback(trace, None, &position), stack_overflow(*max_depth))
+      }
+    }
+  }
+}